@@ -1,4 +1,4 @@
-#![feature(iter_map_windows)]
+use std::collections::HashSet;
 use std::env;
 use std::fs::File;
 use std::io::BufRead;
@@ -92,19 +92,24 @@ impl Line {
     }
 }
 
-fn parse(part_numbers: &mut Vec<PartNumber>) -> Vec<Symbol> {
+fn read_input() -> Vec<String> {
     let file = File::open("input.txt").expect("not found");
     let reader = BufReader::new(file);
-    let re = Regex::new(r"\d+").unwrap();
     reader
         .lines()
-        .enumerate()
-        .map(|(y, l)| {
-            let line = match l {
-                Ok(l) => l,
-                Err(_) => "".to_string(),
-            };
+        .map(|l| match l {
+            Ok(l) => l,
+            Err(_) => "".to_string(),
+        })
+        .collect()
+}
 
+fn parse(lines: &[String], part_numbers: &mut Vec<PartNumber>) -> Vec<Symbol> {
+    let re = Regex::new(r"\d+").unwrap();
+    lines
+        .iter()
+        .enumerate()
+        .flat_map(|(y, line)| {
             re.find_iter(line.as_str()).for_each(|m| {
                 if let Ok(num) = m.as_str().parse::<i64>() {
                     part_numbers.push(PartNumber {
@@ -134,40 +139,27 @@ fn parse(part_numbers: &mut Vec<PartNumber>) -> Vec<Symbol> {
                 })
                 .collect::<Vec<Symbol>>()
         })
-        .flat_map(|f| f)
         .collect::<Vec<Symbol>>()
 }
 
-fn part_1() -> u64 {
+// A number next to several symbols is still a single part
+fn part_1(lines: &[String]) -> u64 {
     let mut part_numbers: Vec<PartNumber> = vec![];
-    let symbols = parse(part_numbers.as_mut());
+    let symbols = parse(lines, part_numbers.as_mut());
 
     part_numbers
         .iter()
-        .map(|p| {
-            symbols
-                .iter()
-                .map(|s| {
-                    if p.line.intersect_rect(&s.rect) {
-                        p.part_number as u64
-                    } else {
-                        0 as u64
-                    }
-                })
-                .sum::<u64>()
-        })
+        .filter(|p| symbols.iter().any(|s| p.line.intersect_rect(&s.rect)))
+        .map(|p| p.part_number as u64)
         .sum::<u64>()
 }
 
-fn part_2() -> i64 {
+fn part_2(lines: &[String]) -> i64 {
     let mut part_numbers: Vec<PartNumber> = vec![];
-    let symbols = parse(part_numbers.as_mut());
+    let symbols = parse(lines, part_numbers.as_mut());
     symbols
         .iter()
-        .filter(|s| match s.c {
-            '*' => true,
-            _ => false,
-        })
+        .filter(|s| matches!(s.c, '*'))
         .map(|s| {
             let nums: Vec<i64> = part_numbers
                 .iter()
@@ -184,6 +176,269 @@ fn part_2() -> i64 {
         .sum::<i64>()
 }
 
+fn is_symbol(c: char) -> bool {
+    !c.is_alphanumeric() && c != '.'
+}
+
+struct Schematic {
+    grid: Vec<Vec<char>>,
+    // Cell -> index into part_numbers for every digit cell
+    owner: Vec<Vec<Option<usize>>>,
+    part_numbers: Vec<Option<PartNumber>>,
+    // Ids of removed numbers, reused so edits don't grow part_numbers
+    free_ids: Vec<usize>,
+    part_sum: u64,
+    gear_sum: u64,
+}
+
+impl Schematic {
+    fn from_lines(lines: &[String]) -> Schematic {
+        let grid: Vec<Vec<char>> = lines.iter().map(|l| l.chars().collect()).collect();
+        let owner = grid.iter().map(|row| vec![None; row.len()]).collect();
+        let mut schematic = Schematic {
+            grid,
+            owner,
+            part_numbers: vec![],
+            free_ids: vec![],
+            part_sum: 0,
+            gear_sum: 0,
+        };
+        for y in 0..schematic.grid.len() {
+            let width = schematic.grid[y].len();
+            if width > 0 {
+                schematic.scan_row(y, 0, width - 1);
+            }
+        }
+        schematic.part_sum = (0..schematic.part_numbers.len())
+            .map(|id| schematic.part_value(id))
+            .sum();
+        schematic.gear_sum = schematic
+            .stars_in(0, schematic.grid.len(), 0, usize::MAX)
+            .iter()
+            .map(|&(x, y)| schematic.gear_value(x, y))
+            .sum();
+        schematic
+    }
+
+    fn part_sum(&self) -> u64 {
+        self.part_sum
+    }
+
+    fn gear_ratio_sum(&self) -> u64 {
+        self.gear_sum
+    }
+
+    fn get(&self, x: usize, y: usize) -> Option<char> {
+        self.grid.get(y).and_then(|row| row.get(x)).copied()
+    }
+
+    // Replaces the cell at coord and returns the previous char, or None if
+    // coord is outside the schematic. Only numbers on the edited row around
+    // coord and symbols in the rows next to it are re-evaluated.
+    fn set(&mut self, coord: &Coordinate, c: char) -> Option<char> {
+        if coord.x < 0 || coord.y < 0 {
+            return None;
+        }
+        let (x, y) = (coord.x as usize, coord.y as usize);
+        let prev = self.get(x, y)?;
+        if prev == c {
+            return Some(prev);
+        }
+
+        // Numbers on the same row touching the cell may be merged or split
+        let touched: Vec<usize> = self.ids_in_row(y, x.saturating_sub(1), x + 1);
+        let (mut lo, mut hi) = (x, x);
+        for id in touched.iter() {
+            let line = &self.part_numbers[*id].as_ref().unwrap().line;
+            lo = lo.min(line.a.x as usize);
+            hi = hi.max(line.b.x as usize);
+        }
+        // Any gear whose neighbours can change lies within this window
+        let gears = self.stars_in(y.saturating_sub(1), y + 2, lo.saturating_sub(1), hi + 2);
+
+        let mut before: HashSet<usize> = self.adjacent_numbers(x, y);
+        before.extend(touched.iter());
+        self.part_sum -= before.iter().map(|&id| self.part_value(id)).sum::<u64>();
+        self.gear_sum -= gears
+            .iter()
+            .map(|&(gx, gy)| self.gear_value(gx, gy))
+            .sum::<u64>();
+
+        for id in touched {
+            self.remove_number(id);
+        }
+        self.grid[y][x] = c;
+        self.scan_row(y, lo, hi);
+
+        let after: HashSet<usize> = self.adjacent_numbers(x, y);
+        let after: HashSet<usize> = after
+            .into_iter()
+            .chain(self.ids_in_row(y, lo, hi))
+            .collect();
+        let gears = self.stars_in(y.saturating_sub(1), y + 2, lo.saturating_sub(1), hi + 2);
+        self.part_sum += after.iter().map(|&id| self.part_value(id)).sum::<u64>();
+        self.gear_sum += gears
+            .iter()
+            .map(|&(gx, gy)| self.gear_value(gx, gy))
+            .sum::<u64>();
+        Some(prev)
+    }
+
+    fn ids_in_row(&self, y: usize, from: usize, to: usize) -> Vec<usize> {
+        let mut ids: Vec<usize> = (from..=to)
+            .filter_map(|x| {
+                self.owner
+                    .get(y)
+                    .and_then(|row| row.get(x))
+                    .copied()
+                    .flatten()
+            })
+            .collect();
+        ids.dedup();
+        ids
+    }
+
+    // Registers every digit run that lies within from..=to on row y
+    fn scan_row(&mut self, y: usize, from: usize, to: usize) {
+        let mut x = from;
+        while x <= to && x < self.grid[y].len() {
+            if !self.grid[y][x].is_ascii_digit() {
+                x += 1;
+                continue;
+            }
+            let start = x;
+            while x < self.grid[y].len() && self.grid[y][x].is_ascii_digit() {
+                x += 1;
+            }
+            let digits: String = self.grid[y][start..x].iter().collect();
+            if let Ok(num) = digits.parse::<i64>() {
+                let part_number = Some(PartNumber {
+                    part_number: num,
+                    line: Line {
+                        a: Coordinate {
+                            x: start as i64,
+                            y: y as i64,
+                        },
+                        b: Coordinate {
+                            x: (x - 1) as i64,
+                            y: y as i64,
+                        },
+                    },
+                });
+                let id = match self.free_ids.pop() {
+                    Some(id) => {
+                        self.part_numbers[id] = part_number;
+                        id
+                    }
+                    None => {
+                        self.part_numbers.push(part_number);
+                        self.part_numbers.len() - 1
+                    }
+                };
+                (start..x).for_each(|i| self.owner[y][i] = Some(id));
+            }
+        }
+    }
+
+    fn remove_number(&mut self, id: usize) {
+        if let Some(p) = self.part_numbers[id].take() {
+            let y = p.line.a.y as usize;
+            (p.line.a.x..=p.line.b.x).for_each(|x| self.owner[y][x as usize] = None);
+            self.free_ids.push(id);
+        }
+    }
+
+    fn neighbours(&self, x: usize, y: usize) -> Vec<(usize, usize)> {
+        (y.saturating_sub(1)..=y + 1)
+            .flat_map(|ny| (x.saturating_sub(1)..=x + 1).map(move |nx| (nx, ny)))
+            .filter(|&(nx, ny)| (nx, ny) != (x, y) && self.get(nx, ny).is_some())
+            .collect()
+    }
+
+    fn adjacent_numbers(&self, x: usize, y: usize) -> HashSet<usize> {
+        self.neighbours(x, y)
+            .into_iter()
+            .filter_map(|(nx, ny)| self.owner[ny][nx])
+            .collect()
+    }
+
+    fn stars_in(
+        &self,
+        y_from: usize,
+        y_to: usize,
+        x_from: usize,
+        x_to: usize,
+    ) -> Vec<(usize, usize)> {
+        (y_from..y_to.min(self.grid.len()))
+            .flat_map(|y| {
+                (x_from..x_to.min(self.grid[y].len()))
+                    .filter(move |&x| self.grid[y][x] == '*')
+                    .map(move |x| (x, y))
+            })
+            .collect()
+    }
+
+    fn part_value(&self, id: usize) -> u64 {
+        let Some(p) = self.part_numbers[id].as_ref() else {
+            return 0;
+        };
+        let y = p.line.a.y as usize;
+        let is_part = (p.line.a.x..=p.line.b.x).any(|x| {
+            self.neighbours(x as usize, y)
+                .into_iter()
+                .any(|(nx, ny)| is_symbol(self.grid[ny][nx]))
+        });
+        if is_part {
+            p.part_number as u64
+        } else {
+            0
+        }
+    }
+
+    fn gear_value(&self, x: usize, y: usize) -> u64 {
+        let nums: Vec<usize> = self.adjacent_numbers(x, y).into_iter().collect();
+        if nums.len() == 2 {
+            nums.iter()
+                .map(|&id| self.part_numbers[id].as_ref().unwrap().part_number as u64)
+                .product()
+        } else {
+            0
+        }
+    }
+}
+
+fn edit(lines: &[String], edits: &[String]) {
+    let mut schematic = Schematic::from_lines(lines);
+    println!("{} {}", schematic.part_sum(), schematic.gear_ratio_sum());
+
+    // Each edit is given as x,y,c
+    for e in edits {
+        let mut fields = e.splitn(3, ',');
+        let coord = match (fields.next(), fields.next()) {
+            (Some(x), Some(y)) => match (x.parse::<i64>(), y.parse::<i64>()) {
+                (Ok(x), Ok(y)) => Coordinate { x, y },
+                _ => {
+                    eprintln!("invalid coordinate in edit: {}", e);
+                    continue;
+                }
+            },
+            _ => {
+                eprintln!("invalid edit: {}", e);
+                continue;
+            }
+        };
+        let Some(c) = fields.next().and_then(|c| c.chars().next()) else {
+            eprintln!("missing char in edit: {}", e);
+            continue;
+        };
+        if schematic.set(&coord, c).is_none() {
+            eprintln!("edit outside schematic: {}", e);
+            continue;
+        }
+        println!("{} {}", schematic.part_sum(), schematic.gear_ratio_sum());
+    }
+}
+
 fn main() {
     let part = match env::var("part") {
         Ok(val) => val,
@@ -191,9 +446,11 @@ fn main() {
     };
 
     if part == "part1" {
-        println!("{}", part_1());
+        println!("{}", part_1(&read_input()));
     } else if part == "part2" {
-        println!("{}", part_2());
+        println!("{}", part_2(&read_input()));
+    } else if part == "edit" {
+        edit(&read_input(), &env::args().skip(1).collect::<Vec<String>>());
     }
 }
 
@@ -233,4 +490,98 @@ mod tests {
 
         assert!(a.intersect_rect(&b));
     }
+
+    fn example() -> Schematic {
+        let lines: Vec<String> = [
+            "467..114..",
+            "...*......",
+            "..35..633.",
+            "......#...",
+            "617*......",
+            ".....+.58.",
+            "..592.....",
+            "......755.",
+            "...$.*....",
+            ".664.598..",
+        ]
+        .iter()
+        .map(|l| l.to_string())
+        .collect();
+        Schematic::from_lines(&lines)
+    }
+
+    // Checks the incremental sums against the line intersection solution
+    fn assert_matches_full(schematic: &Schematic) {
+        let lines: Vec<String> = schematic
+            .grid
+            .iter()
+            .map(|row| row.iter().collect())
+            .collect();
+        assert_eq!(part_1(&lines), schematic.part_sum());
+        assert_eq!(part_2(&lines) as u64, schematic.gear_ratio_sum());
+    }
+
+    #[test]
+    fn schematic_sums() {
+        let schematic = example();
+        assert_eq!(4361, schematic.part_sum());
+        assert_eq!(467835, schematic.gear_ratio_sum());
+    }
+
+    #[test]
+    fn schematic_edits() {
+        let mut schematic = example();
+
+        // Remove the gear between 467 and 35
+        assert_eq!(Some('*'), schematic.set(&Coordinate { x: 3, y: 1 }, '.'));
+        assert_eq!(4361 - 467 - 35, schematic.part_sum());
+        assert_eq!(467835 - 16345, schematic.gear_ratio_sum());
+        assert_matches_full(&schematic);
+
+        // Remove the symbol next to 617
+        assert_eq!(Some('*'), schematic.set(&Coordinate { x: 3, y: 4 }, '.'));
+        assert_eq!(4361 - 467 - 35 - 617, schematic.part_sum());
+        assert_matches_full(&schematic);
+
+        // Join 467 and 114 into a single number
+        schematic.set(&Coordinate { x: 3, y: 0 }, '0');
+        schematic.set(&Coordinate { x: 4, y: 0 }, '0');
+        assert_matches_full(&schematic);
+
+        // Split 633 in two
+        schematic.set(&Coordinate { x: 7, y: 2 }, '.');
+        assert_matches_full(&schematic);
+
+        // Turn the split cell into a gear between 6 and 3
+        schematic.set(&Coordinate { x: 7, y: 2 }, '*');
+        assert_matches_full(&schematic);
+
+        assert_eq!(None, schematic.set(&Coordinate { x: 10, y: 0 }, '*'));
+        assert_eq!(None, schematic.set(&Coordinate { x: -1, y: 0 }, '*'));
+    }
+
+    #[test]
+    fn schematic_random_edits() {
+        let mut schematic = example();
+        let chars = ['.', '.', '*', '*', '#', '1', '2', '5', '9'];
+        let mut seed: u64 = 42;
+        for _ in 0..2000 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let x = (seed >> 33) % 10;
+            let y = (seed >> 40) % 10;
+            let c = chars[((seed >> 50) % chars.len() as u64) as usize];
+            schematic.set(
+                &Coordinate {
+                    x: x as i64,
+                    y: y as i64,
+                },
+                c,
+            );
+            assert_matches_full(&schematic);
+            // At most one number per two cells is live at any time
+            assert!(schematic.part_numbers.len() <= 50);
+        }
+    }
 }
@@ -1,85 +1,381 @@
 use std::env;
+use std::fmt;
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::process;
+use std::str::FromStr;
+use std::time::Instant;
 
-fn part_1() -> u32 {
-    let file = File::open("input.txt").expect("not found");
-    let reader = BufReader::new(file);
+use num::BigUint;
+use num::One;
+use num::Zero;
 
-    reader
-        .lines()
-        .map(|line| {
-            let line = line.unwrap();
-            let (_, cards) = line.split_once(':').unwrap();
-            let (l, r) = cards.split_once('|').unwrap();
-            let win_cards: Vec<u32> = l
-                .split_whitespace()
-                .into_iter()
-                .map(|c| c.parse::<u32>().unwrap())
-                .collect::<Vec<u32>>();
-            let cards: Vec<u32> = r
-                .split_whitespace()
-                .into_iter()
-                .map(|c| c.parse::<u32>().unwrap())
-                .collect::<Vec<u32>>();
-            let mut score = 0;
-            cards
-                .into_iter()
-                .filter(|c| win_cards.contains(c))
-                .enumerate()
-                .for_each(|(i, _)| {
-                    if i > 1 {
-                        score *= 2;
-                    } else {
-                        score += 1;
-                    }
-                });
-            score
+#[derive(Debug, PartialEq)]
+enum ParseError {
+    MissingColon,
+    MissingBar,
+    InvalidId(String),
+    InvalidNumber(String),
+    NumberOutOfRange(u32),
+    DuplicateNumber(u32),
+    UnexpectedId { expected: u32, found: u32 },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingColon => write!(f, "missing ':' after card id"),
+            Self::MissingBar => write!(f, "missing '|' between number lists"),
+            Self::InvalidId(s) => write!(f, "invalid card id '{}'", s),
+            Self::InvalidNumber(s) => write!(f, "invalid number '{}'", s),
+            Self::NumberOutOfRange(n) => {
+                write!(f, "number {} is out of range 0..{}", n, NumberSet::CAPACITY)
+            }
+            Self::DuplicateNumber(n) => write!(f, "number {} is listed twice", n),
+            Self::UnexpectedId { expected, found } => {
+                write!(f, "expected card {} but found card {}", expected, found)
+            }
+        }
+    }
+}
+
+// Set of small card numbers, one bit per number
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+struct NumberSet(u128);
+
+impl NumberSet {
+    const CAPACITY: u32 = u128::BITS;
+
+    fn insert(&mut self, n: u32) -> Result<(), ParseError> {
+        if n >= Self::CAPACITY {
+            return Err(ParseError::NumberOutOfRange(n));
+        }
+        // A repeat would silently count as a single match
+        if self.0 & 1 << n != 0 {
+            return Err(ParseError::DuplicateNumber(n));
+        }
+        self.0 |= 1 << n;
+        Ok(())
+    }
+
+    fn common(&self, other: &Self) -> usize {
+        (self.0 & other.0).count_ones() as usize
+    }
+}
+
+impl FromIterator<u32> for NumberSet {
+    fn from_iter<I: IntoIterator<Item = u32>>(iter: I) -> Self {
+        let mut set = NumberSet::default();
+        iter.into_iter().for_each(|n| set.insert(n).unwrap());
+        set
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct ScratchCard {
+    id: u32,
+    winning: NumberSet,
+    have: NumberSet,
+}
+
+fn parse_numbers(s: &str) -> Result<NumberSet, ParseError> {
+    let mut set = NumberSet::default();
+    for n in s.split_whitespace() {
+        let n = n
+            .parse::<u32>()
+            .map_err(|_| ParseError::InvalidNumber(n.to_string()))?;
+        set.insert(n)?;
+    }
+    Ok(set)
+}
+
+impl FromStr for ScratchCard {
+    type Err = ParseError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let (card, numbers) = line.split_once(':').ok_or(ParseError::MissingColon)?;
+        let id = card
+            .strip_prefix("Card")
+            .and_then(|id| id.trim().parse::<u32>().ok())
+            .ok_or(ParseError::InvalidId(card.to_string()))?;
+        let (winning, have) = numbers.split_once('|').ok_or(ParseError::MissingBar)?;
+        Ok(ScratchCard {
+            id,
+            winning: parse_numbers(winning)?,
+            have: parse_numbers(have)?,
         })
-        .sum()
+    }
+}
+
+impl ScratchCard {
+    fn matches(&self) -> usize {
+        self.winning.common(&self.have)
+    }
 }
 
-fn part_2() -> u32 {
+// Cards are expected to be numbered 1, 2, 3, ... in input order
+fn parse_cards(lines: &[String]) -> Result<Vec<ScratchCard>, (usize, ParseError)> {
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .enumerate()
+        .map(|(i, (line_no, l))| {
+            let card = ScratchCard::from_str(l).map_err(|e| (line_no + 1, e))?;
+            let expected = i as u32 + 1;
+            if card.id != expected {
+                return Err((
+                    line_no + 1,
+                    ParseError::UnexpectedId {
+                        expected,
+                        found: card.id,
+                    },
+                ));
+            }
+            Ok(card)
+        })
+        .collect()
+}
+
+fn parse() -> Vec<ScratchCard> {
     let file = File::open("input.txt").expect("not found");
     let reader = BufReader::new(file);
-    let mut instance_counter: Vec<u32> = vec![];
+    let lines: Vec<String> = reader.lines().map(Result::unwrap).collect();
+    match parse_cards(&lines) {
+        Ok(cards) => cards,
+        Err((line_no, e)) => {
+            eprintln!("input.txt:{}: {}", line_no, e);
+            process::exit(1);
+        }
+    }
+}
+
+// Scores are None when they don't fit in u64
+trait Scoring {
+    fn score(&self, matches: usize) -> Option<u64>;
+}
+
+// 1 point for the first match, doubled for each one after it
+struct Doubling;
+
+impl Scoring for Doubling {
+    fn score(&self, matches: usize) -> Option<u64> {
+        match matches {
+            0 => Some(0),
+            n => 1u64.checked_shl(u32::try_from(n - 1).ok()?),
+        }
+    }
+}
+
+// 1 point per match
+struct Linear;
+
+impl Scoring for Linear {
+    fn score(&self, matches: usize) -> Option<u64> {
+        Some(matches as u64)
+    }
+}
+
+// 1, 2, 3, 5, 8, ... points for 1, 2, 3, 4, 5, ... matches
+struct Fibonacci;
+
+impl Scoring for Fibonacci {
+    fn score(&self, matches: usize) -> Option<u64> {
+        if matches == 0 {
+            return Some(0);
+        }
+        let (mut curr, mut next) = (1u64, Some(2u64));
+        for _ in 1..matches {
+            (curr, next) = (next?, next.and_then(|n| n.checked_add(curr)));
+        }
+        Some(curr)
+    }
+}
+
+// Score looked up by match count, the last entry is used for any higher count
+struct Table(Vec<u64>);
+
+impl Scoring for Table {
+    fn score(&self, matches: usize) -> Option<u64> {
+        Some(
+            self.0
+                .get(matches)
+                .or(self.0.last())
+                .copied()
+                .unwrap_or_default(),
+        )
+    }
+}
+
+fn parse_scoring(s: &str) -> Result<Box<dyn Scoring>, String> {
+    match s {
+        "doubling" => Ok(Box::new(Doubling)),
+        "linear" => Ok(Box::new(Linear)),
+        "fibonacci" => Ok(Box::new(Fibonacci)),
+        _ => {
+            let table = s
+                .strip_prefix("table:")
+                .ok_or(format!("unknown scoring '{}'", s))?;
+            let scores = table
+                .split(',')
+                .map(|n| {
+                    n.trim()
+                        .parse::<u64>()
+                        .map_err(|_| format!("invalid table score '{}'", n))
+                })
+                .collect::<Result<Vec<u64>, String>>()?;
+            Ok(Box::new(Table(scores)))
+        }
+    }
+}
 
-    // Card #, matches
-    let cards_copies: Vec<(u32, u32)> = reader
-        .lines()
+// None when a score or the total overflows u64
+fn part_1(cards: &[ScratchCard], scoring: &dyn Scoring) -> Option<u64> {
+    cards.iter().try_fold(0u64, |acc, card| {
+        acc.checked_add(scoring.score(card.matches())?)
+    })
+}
+
+// Instance counter for the card cascade, u64 unless counts=bigint is set
+trait Count: Clone + fmt::Display {
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn checked_add_assign(&mut self, other: &Self) -> Option<()>;
+}
+
+impl Count for u64 {
+    fn zero() -> Self {
+        0
+    }
+
+    fn one() -> Self {
+        1
+    }
+
+    fn checked_add_assign(&mut self, other: &Self) -> Option<()> {
+        *self = self.checked_add(*other)?;
+        Some(())
+    }
+}
+
+impl Count for BigUint {
+    fn zero() -> Self {
+        Zero::zero()
+    }
+
+    fn one() -> Self {
+        One::one()
+    }
+
+    fn checked_add_assign(&mut self, other: &Self) -> Option<()> {
+        *self += other;
+        Some(())
+    }
+}
+
+// Instances of every card after all copies are won, None on overflow
+fn cascade<T: Count>(cards: &[ScratchCard]) -> Option<Vec<T>> {
+    let mut instance_counter: Vec<T> = vec![T::one(); cards.len()];
+
+    for (i, card) in cards.iter().enumerate() {
+        let curr_card_amount = instance_counter[i].clone();
+        for x in instance_counter.iter_mut().skip(i + 1).take(card.matches()) {
+            x.checked_add_assign(&curr_card_amount)?;
+        }
+    }
+    Some(instance_counter)
+}
+
+fn total<T: Count>(counts: &[T]) -> Option<T> {
+    counts.iter().try_fold(T::zero(), |mut acc, c| {
+        acc.checked_add_assign(c)?;
+        Some(acc)
+    })
+}
+
+fn part_2<T: Count>(cards: &[ScratchCard]) -> Option<T> {
+    total(&cascade::<T>(cards)?)
+}
+
+fn trace<T: Count>(cards: &[ScratchCard]) -> Option<T> {
+    let counts = cascade::<T>(cards)?;
+    for (i, (card, count)) in cards.iter().zip(counts.iter()).enumerate() {
+        let fed = card.matches().min(cards.len() - i - 1);
+        let feeds = match fed {
+            0 => "nothing".to_string(),
+            1 => format!("card {}", cards[i + 1].id),
+            _ => format!("cards {}-{}", cards[i + 1].id, cards[i + fed].id),
+        };
+        println!(
+            "Card {}: {} matches, {} instances, feeds {}",
+            card.id,
+            card.matches(),
+            count,
+            feeds
+        );
+    }
+    total(&counts)
+}
+
+fn print_count<T: Count>(count: Option<T>) {
+    match count {
+        Some(count) => println!("{}", count),
+        None => {
+            eprintln!("card count overflowed u64, rerun with counts=bigint");
+            process::exit(1);
+        }
+    }
+}
+
+// Compares the bitset match count against scanning a Vec of winning numbers
+fn bench(card_count: usize) {
+    let mut seed: u64 = 0x2023_0004;
+    let mut next_random = |bound: usize| {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 33) as usize % bound
+    };
+    // Like real cards, numbers are distinct within each list
+    let mut pick = |count: usize| {
+        let mut pool: Vec<u32> = (1..100).collect();
+        (0..count)
+            .map(|i| {
+                let j = i + next_random(pool.len() - i);
+                pool.swap(i, j);
+                pool[i]
+            })
+            .collect::<Vec<u32>>()
+    };
+    let generated: Vec<(Vec<u32>, Vec<u32>)> =
+        (0..card_count).map(|_| (pick(10), pick(25))).collect();
+
+    let start = Instant::now();
+    let vec_matches: usize = generated
+        .iter()
+        .map(|(winning, have)| have.iter().filter(|c| winning.contains(c)).count())
+        .sum();
+    let vec_time = start.elapsed();
+
+    let cards: Vec<ScratchCard> = generated
+        .iter()
         .enumerate()
-        .map(|(i, line)| {
-            instance_counter.push(1);
-            let line = line.unwrap();
-            let (_, cards) = line.split_once(':').unwrap();
-            let (l, r) = cards.split_once('|').unwrap();
-            let win_cards: Vec<u32> = l
-                .split_whitespace()
-                .into_iter()
-                .map(|c| c.parse::<u32>().unwrap())
-                .collect::<Vec<u32>>();
-            let cards: Vec<u32> = r
-                .split_whitespace()
-                .into_iter()
-                .map(|c| c.parse::<u32>().unwrap())
-                .collect::<Vec<u32>>();
-            (
-                i as u32,
-                cards.into_iter().filter(|c| win_cards.contains(c)).count() as u32,
-            )
+        .map(|(i, (winning, have))| ScratchCard {
+            id: i as u32 + 1,
+            winning: winning.iter().copied().collect(),
+            have: have.iter().copied().collect(),
         })
         .collect();
+    let start = Instant::now();
+    let set_matches: usize = cards.iter().map(ScratchCard::matches).sum();
+    let set_time = start.elapsed();
 
-    for (i, c) in cards_copies {
-        let curr_card_amount = *instance_counter.get_mut(i as usize).unwrap();
-        for j in i + 1..=i + c {
-            if let Some(x) = instance_counter.get_mut(j as usize) {
-                *x = *x + curr_card_amount;
-            }
-        }
-    }
-    instance_counter.into_iter().sum()
+    assert_eq!(vec_matches, set_matches);
+    println!("cards:   {}", card_count);
+    println!("matches: {}", set_matches);
+    println!("vec:     {:?}", vec_time);
+    println!("bitset:  {:?}", set_time);
 }
 
 fn main() {
@@ -89,8 +385,196 @@ fn main() {
     };
 
     if part == "part1" {
-        println!("{}", part_1());
-    } else if part == "part2" {
-        println!("{}", part_2());
+        let scoring = match env::var("scoring") {
+            Ok(val) => parse_scoring(&val).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(1);
+            }),
+            Err(_e) => Box::new(Doubling),
+        };
+        match part_1(&parse(), scoring.as_ref()) {
+            Some(points) => println!("{}", points),
+            None => {
+                eprintln!("points overflowed u64 with this scoring");
+                process::exit(1);
+            }
+        }
+    } else if part == "part2" || part == "trace" {
+        let cards = parse();
+        let bigint = env::var("counts").is_ok_and(|c| c == "bigint");
+        match (part == "trace", bigint) {
+            (false, false) => print_count(part_2::<u64>(&cards)),
+            (false, true) => print_count(part_2::<BigUint>(&cards)),
+            (true, false) => print_count(trace::<u64>(&cards)),
+            (true, true) => print_count(trace::<BigUint>(&cards)),
+        }
+    } else if part == "bench" {
+        let card_count = match env::var("cards") {
+            Ok(val) => val.parse::<usize>().expect("cards must be a number"),
+            Err(_e) => 1_000_000,
+        };
+        bench(card_count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> Vec<String> {
+        [
+            "Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53",
+            "Card 2: 13 32 20 16 61 | 61 30 68 82 17 32 24 19",
+            "Card 3:  1 21 53 59 44 | 69 82 63 72 16 21 14  1",
+            "Card 4: 41 92 73 84 69 | 59 84 76 51 58  5 54 83",
+            "Card 5: 87 83 26 28 32 | 88 30 70 12 93 22 82 36",
+            "Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11",
+        ]
+        .iter()
+        .map(|l| l.to_string())
+        .collect()
+    }
+
+    #[test]
+    fn parse_card() {
+        let card = ScratchCard::from_str("Card  12: 41 48 | 83 86  6").unwrap();
+        assert_eq!(
+            ScratchCard {
+                id: 12,
+                winning: [41, 48].into_iter().collect(),
+                have: [83, 86, 6].into_iter().collect(),
+            },
+            card
+        );
+    }
+
+    #[test]
+    fn parse_malformed_card() {
+        assert_eq!(
+            Err(ParseError::MissingColon),
+            ScratchCard::from_str("Card 1 41 48 | 83 86")
+        );
+        assert_eq!(
+            Err(ParseError::MissingBar),
+            ScratchCard::from_str("Card 1: 41 48 83 86")
+        );
+        assert_eq!(
+            Err(ParseError::InvalidId("Crad 1".to_string())),
+            ScratchCard::from_str("Crad 1: 41 48 | 83 86")
+        );
+        assert_eq!(
+            Err(ParseError::InvalidNumber("4x".to_string())),
+            ScratchCard::from_str("Card 1: 41 4x | 83 86")
+        );
+        assert_eq!(
+            Err(ParseError::NumberOutOfRange(128)),
+            ScratchCard::from_str("Card 1: 41 48 | 83 128")
+        );
+        assert_eq!(
+            Err(ParseError::DuplicateNumber(5)),
+            ScratchCard::from_str("Card 1: 5 6 | 5 5 5")
+        );
+        assert_eq!(
+            Err(ParseError::DuplicateNumber(41)),
+            ScratchCard::from_str("Card 1: 41 41 | 83 86")
+        );
+    }
+
+    #[test]
+    fn number_set_matches() {
+        let card = ScratchCard::from_str("Card 1: 0 5 99 127 | 127 1 5 64 0").unwrap();
+        assert_eq!(3, card.matches());
+    }
+
+    #[test]
+    fn parse_non_sequential_ids() {
+        let mut lines = example();
+        lines.remove(2);
+        assert_eq!(
+            Err((
+                3,
+                ParseError::UnexpectedId {
+                    expected: 3,
+                    found: 4
+                }
+            )),
+            parse_cards(&lines)
+        );
+    }
+
+    #[test]
+    fn example_parts() {
+        let cards = parse_cards(&example()).unwrap();
+        assert_eq!(Some(13), part_1(&cards, &Doubling));
+        assert_eq!(Some(30), part_2::<u64>(&cards));
+        assert_eq!(Some(BigUint::from(30u32)), part_2::<BigUint>(&cards));
+        assert_eq!(Some(vec![1, 2, 4, 8, 14, 1]), cascade::<u64>(&cards));
+    }
+
+    #[test]
+    fn scoring_rules() {
+        let scores = |scoring: &dyn Scoring| {
+            (0..7)
+                .map(|n| scoring.score(n).unwrap())
+                .collect::<Vec<u64>>()
+        };
+        assert_eq!(vec![0, 1, 2, 4, 8, 16, 32], scores(&Doubling));
+        assert_eq!(vec![0, 1, 2, 3, 4, 5, 6], scores(&Linear));
+        assert_eq!(vec![0, 1, 2, 3, 5, 8, 13], scores(&Fibonacci));
+        assert_eq!(vec![0, 1, 3, 3, 3, 3, 3], scores(&Table(vec![0, 1, 3])));
+        assert_eq!(Some(1 << 63), Doubling.score(64));
+        assert_eq!(None, Doubling.score(65));
+        assert_eq!(Some(12200160415121876738), Fibonacci.score(92));
+        assert_eq!(None, Fibonacci.score(93));
+
+        let cards = parse_cards(&example()).unwrap();
+        let table = parse_scoring("table:0, 10, 20").unwrap();
+        assert_eq!(Some(70), part_1(&cards, table.as_ref()));
+        assert_eq!(
+            Some(9),
+            part_1(&cards, parse_scoring("linear").unwrap().as_ref())
+        );
+        assert!(parse_scoring("table:1,x").is_err());
+        assert!(parse_scoring("quadratic").is_err());
+    }
+
+    #[test]
+    fn score_overflow() {
+        // Two cards with 64 matches are worth 2^63 each, their sum overflows
+        let card = |id: u32, matches: u32| ScratchCard {
+            id,
+            winning: (0..matches).collect(),
+            have: (0..matches).collect(),
+        };
+        assert_eq!(Some(1 << 63), part_1(&[card(1, 64)], &Doubling));
+        assert_eq!(None, part_1(&[card(1, 64), card(2, 64)], &Doubling));
+        assert_eq!(None, part_1(&[card(1, 70), card(2, 70)], &Doubling));
+        assert_eq!(None, part_1(&[card(1, 100)], &Fibonacci));
+        assert_eq!(Some(140), part_1(&[card(1, 70), card(2, 70)], &Linear));
+    }
+
+    #[test]
+    fn long_cascade_overflow() {
+        // Every card wins the next 10, so instance counts grow exponentially
+        let cards: Vec<ScratchCard> = (1..=100)
+            .map(|id| ScratchCard {
+                id,
+                winning: (1..=10).collect(),
+                have: (1..=10).collect(),
+            })
+            .collect();
+        assert_eq!(None, part_2::<u64>(&cards));
+
+        let counts = cascade::<BigUint>(&cards).unwrap();
+        assert!(counts[99] > BigUint::from(u64::MAX));
+        let small = cascade::<u64>(&cards[90..]).unwrap();
+        let small_big = cascade::<BigUint>(&cards[90..]).unwrap();
+        assert_eq!(
+            small
+                .into_iter()
+                .map(BigUint::from)
+                .collect::<Vec<BigUint>>(),
+            small_big
+        );
     }
 }
@@ -1,27 +1,14 @@
-use parse_display::Display;
 use parse_display::FromStr;
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::env;
+use std::fmt;
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
 use std::ops::Range;
+use std::process;
 use std::str::FromStr;
-use strum::IntoEnumIterator;
-use strum_macros::EnumIter;
-
-#[derive(Debug, Display, FromStr, Eq, PartialEq, Hash, Clone, EnumIter)]
-#[display(style = "lowercase")]
-enum Category {
-    Seed,
-    Soil,
-    Fertilizer,
-    Water,
-    Light,
-    Temperature,
-    Humidity,
-    Location,
-}
 
 #[derive(Debug, FromStr)]
 #[display("{dest} {src} {range_len}")]
@@ -36,100 +23,670 @@ struct MapRange {
     dest: Range<u64>,
 }
 
-struct SeedMap {
-    seeds: Vec<u64>,
-    map: HashMap<Category, Vec<MapRange>>
-} 
+impl MapRange {
+    fn map(&self, x: u64) -> Option<u64> {
+        if self.src.contains(&x) {
+            Some(self.dest.start + (x - self.src.start))
+        } else {
+            None
+        }
+    }
+}
 
-fn parse() -> SeedMap {
-    let file = File::open("input.txt").expect("not found");
-    let reader = BufReader::new(file);
-    let mut line_iterator = reader.lines();
-    let mut map: HashMap<Category, Vec<MapRange>> = HashMap::new();
-    map.insert(Category::Seed, Vec::new());
+// The first range containing x decides where it goes, anything else maps to itself
+fn map_value(ranges: &[MapRange], x: u64) -> u64 {
+    ranges.iter().find_map(|r| r.map(x)).unwrap_or(x)
+}
 
-    let seeds = line_iterator
-        .next()
-        .unwrap()
-        .unwrap()
-        .split_whitespace()
-        .filter(|f| f.chars().nth(0).unwrap().is_numeric())
-        .map(|f| f.parse::<u64>().unwrap())
-        .collect::<Vec<u64>>();
+// Piece of a RangeMap, src.start maps to dest and the rest follows linearly
+#[derive(Debug, Clone, PartialEq)]
+struct Piece {
+    src: Range<u64>,
+    dest: u64,
+}
+
+impl Piece {
+    fn map(&self, x: u64) -> u64 {
+        self.dest + (x - self.src.start)
+    }
+}
+
+// Piecewise-linear map over u64 with sorted, non-overlapping pieces. Values
+// outside every piece map to themselves.
+#[derive(Debug, Clone, PartialEq, Default)]
+struct RangeMap {
+    pieces: Vec<Piece>,
+}
+
+impl RangeMap {
+    // Overlapping ranges are resolved the same way as map_value
+    fn from_ranges(ranges: &[MapRange]) -> RangeMap {
+        let mut bounds: Vec<u64> = ranges
+            .iter()
+            .flat_map(|r| [r.src.start, r.src.end])
+            .collect();
+        bounds.sort();
+        bounds.dedup();
+        let pieces = bounds
+            .windows(2)
+            .map(|b| Piece {
+                src: b[0]..b[1],
+                dest: map_value(ranges, b[0]),
+            })
+            .collect();
+        RangeMap::from_pieces(pieces)
+    }
 
-    let mut prev_category = Category::Seed;
-    line_iterator.for_each(|l| {
-        let l = l.unwrap().to_string();
-        if let Some((first, _)) = l.split_once("-to-") {
-            if let Ok(category) = Category::from_str(first) {
-                prev_category = category;
+    // Drops identity pieces and joins neighbours that continue each other
+    fn from_pieces(mut pieces: Vec<Piece>) -> RangeMap {
+        pieces.sort_by_key(|p| p.src.start);
+        let mut merged: Vec<Piece> = vec![];
+        for p in pieces
+            .into_iter()
+            .filter(|p| p.src.start != p.dest && !p.src.is_empty())
+        {
+            match merged.last_mut() {
+                Some(last) if last.src.end == p.src.start && last.map(p.src.start) == p.dest => {
+                    last.src.end = p.src.end;
+                }
+                _ => merged.push(p),
             }
-        } else {
-            if let Ok(e) = MapEntry::from_str(&l) {
-                if map.get_key_value(&prev_category).is_none() {
-                    map.insert(prev_category.clone(), Vec::new());
+        }
+        RangeMap { pieces: merged }
+    }
+
+    fn get(&self, x: u64) -> u64 {
+        let i = self.pieces.partition_point(|p| p.src.start <= x);
+        match i.checked_sub(1).map(|i| &self.pieces[i]) {
+            Some(p) if p.src.contains(&x) => p.map(x),
+            _ => x,
+        }
+    }
+
+    // Every piece plus the identity gaps between them, covering 0..u64::MAX
+    fn segments(&self) -> Vec<Piece> {
+        let mut segments: Vec<Piece> = vec![];
+        let mut next = 0;
+        for p in self.pieces.iter() {
+            if next < p.src.start {
+                segments.push(Piece {
+                    src: next..p.src.start,
+                    dest: next,
+                });
+            }
+            segments.push(p.clone());
+            next = p.src.end;
+        }
+        if next < u64::MAX {
+            segments.push(Piece {
+                src: next..u64::MAX,
+                dest: next,
+            });
+        }
+        segments
+    }
+
+    fn map_range(&self, r: Range<u64>) -> Vec<Range<u64>> {
+        let i = self.pieces.partition_point(|p| p.src.end <= r.start);
+        let mut mapped: Vec<Range<u64>> = vec![];
+        let mut next = r.start;
+        for p in self.pieces[i..].iter() {
+            if p.src.start >= r.end {
+                break;
+            }
+            if next < p.src.start {
+                mapped.push(next..p.src.start);
+            }
+            let start = next.max(p.src.start);
+            let end = r.end.min(p.src.end);
+            mapped.push(p.map(start)..p.map(start) + (end - start));
+            next = end;
+        }
+        if next < r.end {
+            mapped.push(next..r.end);
+        }
+        mapped
+    }
+
+    // Map that applies self first and then next
+    fn then(&self, next: &RangeMap) -> RangeMap {
+        let pieces = self
+            .segments()
+            .into_iter()
+            .flat_map(|s| {
+                let len = s.src.end - s.src.start;
+                // Split the image of s wherever next changes piece
+                let image = s.dest..s.dest.saturating_add(len);
+                let mut cuts: Vec<u64> = next
+                    .pieces
+                    .iter()
+                    .flat_map(|p| [p.src.start, p.src.end])
+                    .filter(|b| image.contains(b) && *b != image.start)
+                    .collect();
+                cuts.push(image.start);
+                cuts.push(image.end);
+                cuts.sort();
+                cuts.dedup();
+                cuts.windows(2)
+                    .map(|c| Piece {
+                        src: s.src.start + (c[0] - s.dest)..s.src.start + (c[1] - s.dest),
+                        dest: next.get(c[0]),
+                    })
+                    .collect::<Vec<Piece>>()
+            })
+            .collect();
+        RangeMap::from_pieces(pieces)
+    }
+}
+
+// Inverse of a RangeMap. Sections need not be bijective, so a value can
+// have several preimages or none at all.
+struct InverseMap {
+    // Image of every segment mapped back to where it came from
+    segments: Vec<Piece>,
+}
+
+impl InverseMap {
+    fn new(map: &RangeMap) -> InverseMap {
+        let mut segments: Vec<Piece> = map
+            .segments()
+            .into_iter()
+            .map(|s| Piece {
+                src: s.dest..s.dest.saturating_add(s.src.end - s.src.start),
+                dest: s.src.start,
+            })
+            .collect();
+        segments.sort_by_key(|s| s.src.start);
+        InverseMap { segments }
+    }
+
+    fn preimage(&self, y: u64) -> Vec<u64> {
+        let end = self.segments.partition_point(|s| s.src.start <= y);
+        let mut xs: Vec<u64> = self.segments[..end]
+            .iter()
+            .filter(|s| s.src.contains(&y))
+            .map(|s| s.map(y))
+            .collect();
+        xs.sort();
+        xs
+    }
+
+    // Pairs of (values, their image) for every value in domain that maps below limit
+    fn preimage_below(&self, domain: &[Range<u64>], limit: u64) -> Vec<(Range<u64>, Range<u64>)> {
+        let end = self.segments.partition_point(|s| s.src.start < limit);
+        let mut found: Vec<(Range<u64>, Range<u64>)> = vec![];
+        for s in self.segments[..end].iter() {
+            let from = s.dest..s.map(s.src.end.min(limit));
+            for d in domain {
+                let start = from.start.max(d.start);
+                let end = from.end.min(d.end);
+                if start < end {
+                    let y = s.src.start + (start - s.dest);
+                    found.push((start..end, y..y + (end - start)));
                 }
-                if let Some(entry) = map.get_mut(&prev_category) {
-                    entry.push(MapRange {
-                        src: e.src..(e.src + e.range_len),
-                        dest: e.dest..(e.dest + e.range_len),
+            }
+        }
+        found.sort_by_key(|(_, y)| y.start);
+        found
+    }
+
+    // Values in domain with the n lowest images, ties at the cut-off are all included
+    fn lowest(&self, domain: &[Range<u64>], n: u64) -> Vec<(Range<u64>, Range<u64>)> {
+        let count_below = |limit: u64| -> u64 {
+            self.preimage_below(domain, limit)
+                .iter()
+                .map(|(x, _)| x.end - x.start)
+                .sum()
+        };
+        let (mut lo, mut hi) = (0, u64::MAX);
+        if count_below(hi) < n {
+            return self.preimage_below(domain, hi);
+        }
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if count_below(mid) >= n {
+                hi = mid;
+            } else {
+                lo = mid + 1;
+            }
+        }
+        self.preimage_below(domain, lo)
+    }
+}
+
+impl fmt::Display for RangeMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for p in self.pieces.iter() {
+            let len = p.src.end - p.src.start;
+            writeln!(
+                f,
+                "{}..{} -> {}..{} ({:+})",
+                p.src.start,
+                p.src.end,
+                p.dest,
+                p.dest + len,
+                p.dest as i128 - p.src.start as i128
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, FromStr)]
+#[display("{from}-to-{to} map:")]
+struct Header {
+    from: String,
+    to: String,
+}
+
+struct Section {
+    from: String,
+    to: String,
+    ranges: Vec<MapRange>,
+    // Entries as written in the input, with their line number
+    entries: Vec<(usize, MapEntry)>,
+    // Lines in the section that aren't map entries
+    malformed: Vec<usize>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Issue {
+    ZeroLength {
+        line: usize,
+    },
+    Overflow {
+        line: usize,
+    },
+    Overlap {
+        lines: (usize, usize),
+        src: Range<u64>,
+    },
+    Malformed {
+        line: usize,
+    },
+    NoSection {
+        line: usize,
+    },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ZeroLength { line } => write!(f, "line {}: zero-length range", line),
+            Self::Overflow { line } => write!(f, "line {}: range end overflows u64", line),
+            Self::Overlap { lines, src } => write!(
+                f,
+                "lines {} and {}: source ranges overlap on {}..{}",
+                lines.0, lines.1, src.start, src.end
+            ),
+            Self::Malformed { line } => write!(f, "line {}: not a map entry", line),
+            Self::NoSection { line } => write!(f, "line {}: entry before any map header", line),
+        }
+    }
+}
+
+struct SectionReport {
+    name: String,
+    issues: Vec<Issue>,
+    // Values between the lowest and highest source that no range covers
+    gaps: Vec<Range<u64>>,
+    covered: u64,
+}
+
+impl Section {
+    fn validate(&self) -> SectionReport {
+        let mut issues: Vec<Issue> = self
+            .malformed
+            .iter()
+            .map(|line| Issue::Malformed { line: *line })
+            .collect();
+        for (line, e) in self.entries.iter() {
+            if e.range_len == 0 {
+                issues.push(Issue::ZeroLength { line: *line });
+            }
+            if e.src.checked_add(e.range_len).is_none() || e.dest.checked_add(e.range_len).is_none()
+            {
+                issues.push(Issue::Overflow { line: *line });
+            }
+        }
+
+        let lines: Vec<usize> = self.entries.iter().map(|(line, _)| *line).collect();
+        for (i, a) in self.ranges.iter().enumerate() {
+            for (j, b) in self.ranges.iter().enumerate().skip(i + 1) {
+                let start = a.src.start.max(b.src.start);
+                let end = a.src.end.min(b.src.end);
+                if start < end {
+                    issues.push(Issue::Overlap {
+                        lines: (lines[i], lines[j]),
+                        src: start..end,
                     });
                 }
             }
         }
-    });
 
-    SeedMap {
-        seeds,
-        map,
+        let mut sources: Vec<Range<u64>> = self
+            .ranges
+            .iter()
+            .filter(|r| !r.src.is_empty())
+            .map(|r| r.src.clone())
+            .collect();
+        sources.sort_by_key(|r| r.start);
+        let mut gaps: Vec<Range<u64>> = vec![];
+        let mut covered = 0;
+        let mut next = sources.first().map(|r| r.start).unwrap_or_default();
+        for r in sources {
+            if next < r.start {
+                gaps.push(next..r.start);
+            }
+            if next < r.end {
+                covered += r.end - next.max(r.start);
+                next = r.end;
+            }
+        }
+
+        SectionReport {
+            name: format!("{}-to-{}", self.from, self.to),
+            issues,
+            gaps,
+            covered,
+        }
     }
 }
 
-fn part_1() -> u64 {
-    let seed_map = parse();
+#[derive(Debug, PartialEq)]
+enum ChainError {
+    UnknownCategory(String),
+    NoPath { from: String, to: String },
+    // More than one section converts from this category
+    Ambiguous(String),
+    Cycle(Vec<String>),
+    // Sections that can't be reached by following the chain from its start
+    Gap { end: String, unused: Vec<String> },
+    NoSeeds,
+    // Seed ranges need a start and a length for each range
+    OddSeeds(usize),
+    SeedRangeOverflow { start: u64, len: u64 },
+}
 
-    seed_map.seeds.iter().map(|s| {
-        let s = s.clone();
-        let min = Category::iter()
-        .filter(|c| c != &Category::Location)
-        .fold(s, |acc, c| {
-            seed_map.map.get(&c).unwrap().into_iter().fold(acc, |acc2, f| {
-                if f.src.contains(&acc) {
-                    match acc2 >= f.dest.end && acc2 >= f.src.end {
-                        true => acc2 - f.src.end - f.dest.end,
-                        false => f.dest.end - (f.src.end - acc2),
-                    }
-                } else {
-                    acc2
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownCategory(c) => write!(f, "unknown category '{}'", c),
+            Self::NoPath { from, to } => write!(f, "no maps lead from '{}' to '{}'", from, to),
+            Self::Ambiguous(c) => write!(f, "more than one map starts at '{}'", c),
+            Self::Cycle(path) => write!(f, "maps form a cycle: {}", path.join(" -> ")),
+            Self::Gap { end, unused } => write!(
+                f,
+                "chain stops at '{}', unreachable maps: {}",
+                end,
+                unused.join(", ")
+            ),
+            Self::NoSeeds => write!(f, "no seeds to look up"),
+            Self::OddSeeds(n) => write!(f, "{} seed values don't form start and length pairs", n),
+            Self::SeedRangeOverflow { start, len } => {
+                write!(f, "seed range {} of length {} overflows u64", start, len)
+            }
+        }
+    }
+}
+
+struct SeedMap {
+    // Category the first line lists values of, "seed" for "seeds:"
+    start: String,
+    seeds: Vec<u64>,
+    sections: Vec<Section>,
+    // Lines between the seeds and the first header
+    orphans: Vec<usize>,
+}
+
+impl SeedMap {
+    fn has_category(&self, category: &str) -> bool {
+        self.start == category
+            || self
+                .sections
+                .iter()
+                .any(|s| s.from == category || s.to == category)
+    }
+
+    // Follows the maps from the start category until one has no map leading
+    // out of it. Every section must be used exactly once on the way.
+    fn chain(&self) -> Result<Vec<&Section>, ChainError> {
+        let mut chain: Vec<&Section> = vec![];
+        let mut visited: Vec<&str> = vec![self.start.as_str()];
+        loop {
+            let current = visited.last().unwrap();
+            let mut next = self.sections.iter().filter(|s| &s.from == current);
+            let Some(section) = next.next() else {
+                break;
+            };
+            if next.next().is_some() {
+                return Err(ChainError::Ambiguous(current.to_string()));
+            }
+            if visited.contains(&section.to.as_str()) {
+                let mut cycle: Vec<String> = visited.iter().map(|c| c.to_string()).collect();
+                cycle.push(section.to.clone());
+                return Err(ChainError::Cycle(cycle));
+            }
+            visited.push(section.to.as_str());
+            chain.push(section);
+        }
+        if chain.len() < self.sections.len() {
+            return Err(ChainError::Gap {
+                end: visited.last().unwrap().to_string(),
+                unused: self
+                    .sections
+                    .iter()
+                    .filter(|s| !chain.iter().any(|c| std::ptr::eq(*c, *s)))
+                    .map(|s| format!("{}-to-{}", s.from, s.to))
+                    .collect(),
+            });
+        }
+        Ok(chain)
+    }
+
+    // Shortest sequence of sections converting from one category to another
+    fn path(&self, from: &str, to: &str) -> Result<Vec<&Section>, ChainError> {
+        for c in [from, to] {
+            if !self.has_category(c) {
+                return Err(ChainError::UnknownCategory(c.to_string()));
+            }
+        }
+        let mut reached: HashMap<&str, Option<&Section>> = HashMap::new();
+        reached.insert(from, None);
+        let mut queue: VecDeque<&str> = VecDeque::from([from]);
+        while let Some(current) = queue.pop_front() {
+            if current == to {
+                let mut path: Vec<&Section> = vec![];
+                let mut c = current;
+                while let Some(Some(section)) = reached.get(c) {
+                    path.push(section);
+                    c = section.from.as_str();
                 }
+                path.reverse();
+                return Ok(path);
+            }
+            for section in self.sections.iter().filter(|s| s.from == current) {
+                if !reached.contains_key(section.to.as_str()) {
+                    reached.insert(section.to.as_str(), Some(section));
+                    queue.push_back(section.to.as_str());
+                }
+            }
+        }
+        Err(ChainError::NoPath {
+            from: from.to_string(),
+            to: to.to_string(),
+        })
+    }
+
+    fn compose(sections: &[&Section]) -> RangeMap {
+        sections.iter().fold(RangeMap::default(), |acc, s| {
+            acc.then(&RangeMap::from_ranges(&s.ranges))
+        })
+    }
+
+    fn map_between(&self, from: &str, to: &str) -> Result<RangeMap, ChainError> {
+        Ok(SeedMap::compose(&self.path(from, to)?))
+    }
+
+    // Every section of the chain composed into a single seed to location map
+    fn location_map(&self) -> Result<RangeMap, ChainError> {
+        Ok(SeedMap::compose(&self.chain()?))
+    }
+
+    // Seeds are given as pairs of range start and length
+    fn seed_ranges(&self) -> Result<Vec<Range<u64>>, ChainError> {
+        let pairs = self.seeds.chunks_exact(2);
+        if !pairs.remainder().is_empty() {
+            return Err(ChainError::OddSeeds(self.seeds.len()));
+        }
+        pairs
+            .map(|s| {
+                let end = s[0]
+                    .checked_add(s[1])
+                    .ok_or(ChainError::SeedRangeOverflow {
+                        start: s[0],
+                        len: s[1],
+                    })?;
+                Ok(s[0]..end)
             })
-        });
-        min
-    }).min().unwrap()
-}
-
-fn part_2() -> u64 {
-    let seed_map = parse();
-
-    seed_map.seeds.windows(2).map(|s| {
-        (s[0]..=(&s[0]+&s[1])).into_iter().map(|s| {
-        let min = Category::iter()
-        .filter(|c| c != &Category::Location)
-        .fold(s, |acc, c| {
-            seed_map.map.get(&c).unwrap().into_iter().fold(acc, |acc2, f| {
-                if f.src.contains(&acc) {
-                    match acc2 >= f.dest.end && acc2 >= f.src.end {
-                        true => acc2 - f.src.end - f.dest.end,
-                        false => f.dest.end - (f.src.end - acc2),
+            .collect()
+    }
+}
+
+fn parse() -> SeedMap {
+    let file = File::open("input.txt").expect("not found");
+    let reader = BufReader::new(file);
+    let lines: Vec<String> = reader.lines().map(Result::unwrap).collect();
+    parse_lines(&lines).unwrap_or_else(|(line_no, e)| {
+        eprintln!("input.txt:{}: {}", line_no, e);
+        process::exit(1);
+    })
+}
+
+#[derive(Debug, PartialEq)]
+enum ParseError {
+    MissingSeeds,
+    InvalidSeed(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingSeeds => write!(f, "expected 'seeds:' followed by numbers"),
+            Self::InvalidSeed(s) => write!(f, "invalid seed '{}'", s),
+        }
+    }
+}
+
+// Errors carry the line number. Lines after the seeds that aren't headers or
+// entries are kept for validate to report.
+fn parse_lines(lines: &[String]) -> Result<SeedMap, (usize, ParseError)> {
+    let mut line_iterator = lines.iter();
+    let mut sections: Vec<Section> = vec![];
+    let mut orphans: Vec<usize> = vec![];
+
+    let (start, seeds) = line_iterator
+        .next()
+        .and_then(|l| l.split_once(':'))
+        .ok_or((1, ParseError::MissingSeeds))?;
+    let start = start.strip_suffix('s').unwrap_or(start).to_string();
+    let seeds = seeds
+        .split_whitespace()
+        .map(|f| {
+            f.parse::<u64>()
+                .map_err(|_| (1, ParseError::InvalidSeed(f.to_string())))
+        })
+        .collect::<Result<Vec<u64>, (usize, ParseError)>>()?;
+
+    line_iterator
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .for_each(|(i, l)| {
+            let line_no = i + 2;
+            if let Ok(header) = Header::from_str(l) {
+                sections.push(Section {
+                    from: header.from,
+                    to: header.to,
+                    ranges: vec![],
+                    entries: vec![],
+                    malformed: vec![],
+                });
+            } else if let Some(section) = sections.last_mut() {
+                match MapEntry::from_str(l) {
+                    Ok(e) => {
+                        // Ranges running past u64::MAX are cut short, validate reports them
+                        let len = e.range_len.min(u64::MAX - e.src).min(u64::MAX - e.dest);
+                        section.ranges.push(MapRange {
+                            src: e.src..(e.src + len),
+                            dest: e.dest..(e.dest + len),
+                        });
+                        section.entries.push((line_no, e));
                     }
-                } else {
-                    acc2
+                    // Kept for validate, which reports them
+                    Err(_) => section.malformed.push(line_no),
                 }
-            })
+            } else {
+                orphans.push(line_no);
+            }
         });
-        min
-        }).min().unwrap()
-    }).min().unwrap()
+
+    Ok(SeedMap {
+        start,
+        seeds,
+        sections,
+        orphans,
+    })
+}
+
+fn part_1(seed_map: &SeedMap) -> Result<u64, ChainError> {
+    let location_map = seed_map.location_map()?;
+    seed_map
+        .seeds
+        .iter()
+        .map(|s| location_map.get(*s))
+        .min()
+        .ok_or(ChainError::NoSeeds)
+}
+
+fn part_2(seed_map: &SeedMap) -> Result<u64, ChainError> {
+    let location_map = seed_map.location_map()?;
+    seed_map
+        .seed_ranges()?
+        .into_iter()
+        .flat_map(|r| location_map.map_range(r))
+        .map(|r| r.start)
+        .min()
+        .ok_or(ChainError::NoSeeds)
+}
+
+fn validate(seed_map: &SeedMap) -> bool {
+    let mut valid = true;
+    if let Err(e) = seed_map.chain() {
+        println!("{}", e);
+        valid = false;
+    }
+    for line in seed_map.orphans.iter() {
+        println!("{}", Issue::NoSection { line: *line });
+        valid = false;
+    }
+    for report in seed_map.sections.iter().map(Section::validate) {
+        println!("{}: {} values covered", report.name, report.covered);
+        for issue in report.issues.iter() {
+            println!("  {}", issue);
+        }
+        for gap in report.gaps.iter() {
+            println!("  gap {}..{} maps to itself", gap.start, gap.end);
+        }
+        valid &= report.issues.is_empty();
+    }
+    valid
+}
+
+fn or_exit<T>(result: Result<T, ChainError>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    })
 }
 
 fn main() {
@@ -139,8 +696,457 @@ fn main() {
     };
 
     if part == "part1" {
-        println!("{}", part_1());
+        println!("{}", or_exit(part_1(&parse())));
     } else if part == "part2" {
-        println!("{}", part_2());
+        println!("{}", or_exit(part_2(&parse())));
+    } else if part == "map" {
+        print!("{}", or_exit(parse().location_map()));
+    } else if part == "validate" {
+        if !validate(&parse()) {
+            process::exit(1);
+        }
+    } else if part == "path" {
+        let seed_map = parse();
+        let from = env::var("from").unwrap_or(seed_map.start.clone());
+        let to = env::var("to").expect("to not set");
+        let path = or_exit(seed_map.path(&from, &to));
+        let mut categories: Vec<&str> = vec![from.as_str()];
+        categories.extend(path.iter().map(|s| s.to.as_str()));
+        println!("{}", categories.join(" -> "));
+        if let Ok(val) = env::var("value") {
+            let value = val.parse::<u64>().expect("value must be a number");
+            println!("{}", or_exit(seed_map.map_between(&from, &to)).get(value));
+        }
+    } else if part == "seeds" {
+        let location = env::var("location")
+            .expect("location not set")
+            .parse::<u64>()
+            .expect("location must be a number");
+        let inverse = InverseMap::new(&or_exit(parse().location_map()));
+        inverse
+            .preimage(location)
+            .iter()
+            .for_each(|s| println!("{}", s));
+    } else if part == "lowest" {
+        let n = match env::var("n") {
+            Ok(val) => val.parse::<u64>().expect("n must be a number"),
+            Err(_e) => 1,
+        };
+        let seed_map = parse();
+        let inverse = InverseMap::new(&or_exit(seed_map.location_map()));
+        for (seeds, locations) in inverse.lowest(&or_exit(seed_map.seed_ranges()), n) {
+            println!(
+                "seeds {}..{} -> locations {}..{}",
+                seeds.start, seeds.end, locations.start, locations.end
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+
+    fn try_almanac(input: &str) -> Result<SeedMap, (usize, ParseError)> {
+        parse_lines(
+            &input
+                .lines()
+                .map(|l| l.to_string())
+                .collect::<Vec<String>>(),
+        )
+    }
+
+    fn almanac(input: &str) -> SeedMap {
+        try_almanac(input).unwrap()
+    }
+
+    fn example() -> SeedMap {
+        let input = "seeds: 79 14 55 13
+
+seed-to-soil map:
+50 98 2
+52 50 48
+
+soil-to-fertilizer map:
+0 15 37
+37 52 2
+39 0 15
+
+fertilizer-to-water map:
+49 53 8
+0 11 42
+42 0 7
+57 7 4
+
+water-to-light map:
+88 18 7
+18 25 70
+
+light-to-temperature map:
+45 77 23
+81 45 19
+68 64 13
+
+temperature-to-humidity map:
+0 69 1
+1 0 69
+
+humidity-to-location map:
+60 56 37
+56 93 4";
+        almanac(input)
+    }
+
+    #[test]
+    fn example_parts() {
+        let seed_map = example();
+        assert_eq!(Ok(35), part_1(&seed_map));
+        assert_eq!(Ok(46), part_2(&seed_map));
+    }
+
+    // Applies every section one after another, like the almanac describes
+    fn step_by_step(seed_map: &SeedMap, seed: u64) -> u64 {
+        seed_map
+            .sections
+            .iter()
+            .fold(seed, |acc, s| map_value(&s.ranges, acc))
+    }
+
+    #[test]
+    fn composed_map_matches_step_by_step() {
+        let seed_map = example();
+        let location_map = seed_map.location_map().unwrap();
+        for seed in 0..200 {
+            assert_eq!(step_by_step(&seed_map, seed), location_map.get(seed));
+        }
+        assert_eq!(u64::MAX - 1, location_map.get(u64::MAX - 1));
+    }
+
+    #[test]
+    fn intervals_match_brute_force() {
+        let location_map = example().location_map().unwrap();
+        for start in 0..110 {
+            for len in [1, 2, 7, 30] {
+                let brute = (start..start + len)
+                    .map(|s| location_map.get(s))
+                    .min()
+                    .unwrap();
+                let ranges = location_map.map_range(start..start + len);
+                assert_eq!(len, ranges.iter().map(|r| r.end - r.start).sum::<u64>());
+                assert_eq!(brute, ranges.iter().map(|r| r.start).min().unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn split_interval() {
+        let map = RangeMap::from_ranges(&[MapRange {
+            src: 10..20,
+            dest: 100..110,
+        }]);
+        assert_eq!(vec![5..10, 100..110, 20..25], map.map_range(5..25));
+        assert_eq!(vec![102..105], map.map_range(12..15));
+    }
+
+    #[test]
+    fn compose_maps() {
+        let first = RangeMap::from_ranges(&[
+            MapRange {
+                src: 0..10,
+                dest: 10..20,
+            },
+            // Overlapped part is shadowed by the range above
+            MapRange {
+                src: 5..15,
+                dest: 105..115,
+            },
+        ]);
+        assert_eq!(
+            vec![
+                Piece {
+                    src: 0..10,
+                    dest: 10
+                },
+                Piece {
+                    src: 10..15,
+                    dest: 110
+                }
+            ],
+            first.pieces
+        );
+        let second = RangeMap::from_ranges(&[MapRange {
+            src: 15..20,
+            dest: 0..5,
+        }]);
+        let composed = first.then(&second);
+        assert_eq!(
+            vec![
+                Piece {
+                    src: 0..5,
+                    dest: 10
+                },
+                Piece {
+                    src: 5..10,
+                    dest: 0
+                },
+                Piece {
+                    src: 10..15,
+                    dest: 110
+                },
+                Piece {
+                    src: 15..20,
+                    dest: 0
+                },
+            ],
+            composed.pieces
+        );
+        for x in 0..200 {
+            assert_eq!(second.get(first.get(x)), composed.get(x));
+        }
+    }
+
+    #[test]
+    fn inverse_round_trip() {
+        let location_map = example().location_map().unwrap();
+        let inverse = InverseMap::new(&location_map);
+        for seed in 0..200 {
+            assert!(inverse.preimage(location_map.get(seed)).contains(&seed));
+        }
+        for location in 0..200 {
+            for seed in inverse.preimage(location) {
+                assert_eq!(location, location_map.get(seed));
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_non_bijective() {
+        // 0..5 and 10..15 both land on 10..15, nothing lands on 0..5
+        let map = RangeMap::from_ranges(&[MapRange {
+            src: 0..5,
+            dest: 10..15,
+        }]);
+        let inverse = InverseMap::new(&map);
+        assert_eq!(vec![2, 12], inverse.preimage(12));
+        assert_eq!(Vec::<u64>::new(), inverse.preimage(3));
+        assert_eq!(vec![20], inverse.preimage(20));
+    }
+
+    #[test]
+    fn lowest_locations() {
+        let seed_map = example();
+        let location_map = seed_map.location_map().unwrap();
+        let inverse = InverseMap::new(&location_map);
+        let seeds = seed_map.seed_ranges().unwrap();
+
+        let mut brute: Vec<u64> = seeds
+            .iter()
+            .flat_map(|r| r.clone())
+            .map(|s| location_map.get(s))
+            .collect();
+        brute.sort();
+        for n in [1, 2, 5, 13, 27] {
+            let lowest = inverse.lowest(&seeds, n);
+            let mut locations: Vec<u64> = lowest
+                .iter()
+                .flat_map(|(x, y)| {
+                    assert_eq!(location_map.get(x.start), y.start);
+                    y.clone()
+                })
+                .collect();
+            locations.sort();
+            assert_eq!(brute[..n as usize], locations[..]);
+        }
+        assert_eq!(46, inverse.lowest(&seeds, 1)[0].1.start);
+    }
+
+    #[test]
+    fn chain_from_headers() {
+        // Sections out of order and with made up category names
+        let seed_map = almanac(
+            "apples: 1 2 3
+
+pear-to-plum map:
+100 0 10
+
+apple-to-pear map:
+5 0 5",
+        );
+        let chain = seed_map.chain().unwrap();
+        assert_eq!(
+            vec!["pear", "plum"],
+            chain.iter().map(|s| s.to.as_str()).collect::<Vec<&str>>()
+        );
+        assert_eq!(Ok(106), part_1(&seed_map));
+        assert_eq!(7, seed_map.map_between("apple", "pear").unwrap().get(2));
+        assert_eq!(
+            Err(ChainError::NoPath {
+                from: "plum".to_string(),
+                to: "apple".to_string()
+            }),
+            seed_map.path("plum", "apple").map(|_| ())
+        );
+        assert_eq!(
+            Err(ChainError::UnknownCategory("fig".to_string())),
+            seed_map.path("apple", "fig").map(|_| ())
+        );
+    }
+
+    #[test]
+    fn chain_path() {
+        let seed_map = example();
+        let path = seed_map.path("soil", "light").unwrap();
+        assert_eq!(
+            vec!["fertilizer", "water", "light"],
+            path.iter().map(|s| s.to.as_str()).collect::<Vec<&str>>()
+        );
+        assert_eq!(0, seed_map.path("water", "water").unwrap().len());
+    }
+
+    #[test]
+    fn chain_errors() {
+        let gap = almanac(
+            "seeds: 1
+
+seed-to-soil map:
+fertilizer-to-water map:",
+        );
+        assert_eq!(
+            Err(ChainError::Gap {
+                end: "soil".to_string(),
+                unused: vec!["fertilizer-to-water".to_string()]
+            }),
+            gap.chain().map(|_| ())
+        );
+
+        let cycle = almanac(
+            "seeds: 1
+
+seed-to-soil map:
+soil-to-water map:
+water-to-soil map:",
+        );
+        assert_eq!(
+            Err(ChainError::Cycle(vec![
+                "seed".to_string(),
+                "soil".to_string(),
+                "water".to_string(),
+                "soil".to_string()
+            ])),
+            cycle.chain().map(|_| ())
+        );
+
+        let ambiguous = almanac(
+            "seeds: 1
+
+seed-to-soil map:
+seed-to-water map:",
+        );
+        assert_eq!(
+            Err(ChainError::Ambiguous("seed".to_string())),
+            ambiguous.chain().map(|_| ())
+        );
+    }
+
+    #[test]
+    fn validate_sections() {
+        let seed_map = almanac(
+            "seeds: 1
+
+seed-to-soil map:
+50 98 2
+52 50 48
+
+soil-to-water map:
+0 10 10
+100 15 10
+7 40 0
+5 18446744073709551610 10
+0 30 5",
+        );
+        let clean = seed_map.sections[0].validate();
+        assert_eq!("seed-to-soil", clean.name);
+        assert!(clean.issues.is_empty());
+        assert!(clean.gaps.is_empty());
+        assert_eq!(50, clean.covered);
+
+        let report = seed_map.sections[1].validate();
+        assert_eq!(
+            vec![
+                Issue::ZeroLength { line: 10 },
+                Issue::Overflow { line: 11 },
+                Issue::Overlap {
+                    lines: (8, 9),
+                    src: 15..20
+                },
+            ],
+            report.issues
+        );
+        assert_eq!(vec![25..30, 35..18446744073709551610], report.gaps);
+        assert_eq!(15 + 5 + 5, report.covered);
+        // Overflowing range is cut short at u64::MAX
+        assert_eq!(
+            18446744073709551610..u64::MAX,
+            seed_map.sections[1].ranges[3].src
+        );
+        assert_eq!(5..10, seed_map.sections[1].ranges[3].dest);
+        assert!(!validate(&seed_map));
+    }
+
+    #[test]
+    fn validate_stray_lines() {
+        let seed_map = almanac(
+            "seeds: 1
+50 98 2
+
+seed-to-soil map:
+50 98
+1 2 99999999999999999999999
+52 50 48",
+        );
+        assert_eq!(vec![2], seed_map.orphans);
+        assert_eq!(
+            vec![Issue::Malformed { line: 5 }, Issue::Malformed { line: 6 }],
+            seed_map.sections[0].validate().issues
+        );
+        assert!(!validate(&seed_map));
+
+        let seed_map = almanac("seeds: 1\n\nseed-to-soil map:\n52 50 48");
+        assert!(validate(&seed_map));
+    }
+
+    #[test]
+    fn seed_errors() {
+        assert_eq!(
+            Err((1, ParseError::MissingSeeds)),
+            try_almanac("79 14").map(|_| ())
+        );
+        assert_eq!(
+            Err((1, ParseError::MissingSeeds)),
+            try_almanac("").map(|_| ())
+        );
+        assert_eq!(
+            Err((1, ParseError::InvalidSeed("x".to_string()))),
+            try_almanac("seeds: 79 x").map(|_| ())
+        );
+
+        let no_seeds = almanac("seeds:\n\nseed-to-soil map:\n52 50 48");
+        assert_eq!(Err(ChainError::NoSeeds), part_1(&no_seeds));
+        assert_eq!(Err(ChainError::NoSeeds), part_2(&no_seeds));
+        let odd = almanac("seeds: 79 14 55\n\nseed-to-soil map:\n52 50 48");
+        assert_eq!(Ok(14), part_1(&odd));
+        assert_eq!(Err(ChainError::OddSeeds(3)), part_2(&odd));
+        let overflow = almanac(&format!(
+            "seeds: {} 2\n\nseed-to-soil map:\n52 50 48",
+            u64::MAX
+        ));
+        assert_eq!(
+            Err(ChainError::SeedRangeOverflow {
+                start: u64::MAX,
+                len: 2
+            }),
+            part_2(&overflow)
+        );
     }
 }
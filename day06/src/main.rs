@@ -1,91 +1,313 @@
 use std::env;
+use std::fmt;
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::process;
 
-struct Race {
-    time: u64,
-    distance: u64,
+use num::BigInt;
+use num::BigUint;
+use num::One;
+use num::ToPrimitive;
+use num::Zero;
+
+struct Race<N = u64> {
+    time: N,
+    distance: N,
 }
 
-fn parse_part_1() -> Vec<Race> {
-    let file = File::open("input.txt").expect("not found");
-    let reader = BufReader::new(file);
-    let lines: Vec<String> = reader.lines().map(Result::unwrap).collect();
-    lines
-        .chunks(2)
-        .flat_map(|s| {
-            s.get(0)
-                .unwrap()
-                .as_str()
-                .split_whitespace()
-                .filter(|f| f.chars().nth(0).unwrap().is_numeric())
-                .map(|f| f.parse::<u64>().unwrap())
-                .zip(
-                    s.get(1)
-                        .unwrap()
-                        .as_str()
-                        .split_whitespace()
-                        .filter(|f| f.chars().nth(0).unwrap().is_numeric())
-                        .map(|f| f.parse::<u64>().unwrap()),
-                )
-                .map(|(time, distance)| Race { time, distance })
-                .collect::<Vec<Race>>()
-        })
-        .collect::<Vec<Race>>()
+// Largest x with x * x <= n
+fn isqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+    // Newton's method from a starting point that is never below the root
+    let mut x = 1u128 << (128 - n.leading_zeros()).div_ceil(2);
+    loop {
+        let y = (x + n / x) / 2;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
 }
 
-fn parse_part_2() -> Race {
-    let file = File::open("input.txt").expect("not found");
-    let reader = BufReader::new(file);
-    let lines: Vec<String> = reader.lines().map(Result::unwrap).collect();
-    let num = lines
-        .into_iter()
-        .map(|l| {
-            l.split_whitespace()
-                .collect::<Vec<_>>()
-                .join("")
-                .split_once(':')
-                .unwrap()
-                .1
-                .to_string()
+impl Race {
+    fn wins(&self, hold_time: u64) -> bool {
+        let hold_time = hold_time as u128;
+        hold_time * (self.time as u128 - hold_time) > self.distance as u128
+    }
+
+    fn distance_for(&self, hold_time: u64) -> u128 {
+        hold_time as u128 * (self.time - hold_time) as u128
+    }
+
+    // Winning hold times h solve h * (time - h) > distance, which is the
+    // interval strictly between the roots of h^2 - time * h + distance
+    fn winning_interval(&self) -> Option<(u64, u64)> {
+        let (time, distance) = (self.time as u128, self.distance as u128);
+        if !self.wins(self.time / 2) {
+            return None;
+        }
+        let root = isqrt(time * time - 4 * distance);
+        // Rounding in isqrt leaves the first winner at most a step away
+        let mut first = ((time - root) / 2) as u64;
+        while !self.wins(first) {
+            first += 1;
+        }
+        while first > 0 && self.wins(first - 1) {
+            first -= 1;
+        }
+        // Hold times are symmetric around time / 2
+        Some((first, self.time - first))
+    }
+
+    fn ways_to_win(&self) -> u64 {
+        match self.winning_interval() {
+            Some((first, last)) => last - first + 1,
+            None => 0,
+        }
+    }
+
+    // Distance per hold time, '#' beats the record, 'o' does not and '-'
+    // marks the record itself
+    fn plot(&self, width: usize, height: usize) -> String {
+        // Clamped in u64 first, time + 1 columns may not fit in usize
+        let columns = self.time.min(width.saturating_sub(1) as u64) as usize + 1;
+        let height = height.max(1);
+        let hold_times: Vec<u64> = (0..columns)
+            .map(|c| match columns {
+                1 => 0,
+                _ => (c as u128 * self.time as u128 / (columns - 1) as u128) as u64,
+            })
+            .collect();
+        // Scaled to the sampled peak, time / 2 may fall between columns
+        let top = hold_times
+            .iter()
+            .map(|h| self.distance_for(*h))
+            .max()
+            .unwrap_or(0)
+            .max(self.distance as u128)
+            .max(1);
+        // Distances near u128::MAX / 4 overflow d * height in u128
+        let row_of = |d: u128| (BigUint::from(d) * (height - 1) / top).to_usize().unwrap();
+        let record_row = row_of(self.distance as u128);
+
+        let label_width = top.to_string().len();
+        let mut plot = String::new();
+        for row in (0..height).rev() {
+            let label = match row {
+                _ if row == height - 1 => top.to_string(),
+                _ if row == record_row => self.distance.to_string(),
+                0 => "0".to_string(),
+                _ => String::new(),
+            };
+            plot.push_str(&format!("{:>w$} |", label, w = label_width));
+            for h in hold_times.iter() {
+                plot.push(
+                    match (row_of(self.distance_for(*h)) == row, self.wins(*h)) {
+                        (true, true) => '#',
+                        (true, false) => 'o',
+                        _ if row == record_row => '-',
+                        _ => ' ',
+                    },
+                );
+            }
+            plot.push('\n');
+        }
+        plot.push_str(&format!(
+            "{:>w$} +{}\n",
+            "",
+            "-".repeat(columns),
+            w = label_width
+        ));
+        plot.push_str(&format!(
+            "{:>w$}  0{:>c$}\n",
+            "",
+            self.time,
+            w = label_width,
+            c = columns.saturating_sub(1)
+        ));
+        plot
+    }
+}
+
+impl Race<BigUint> {
+    fn wins(&self, hold_time: &BigUint) -> bool {
+        hold_time * (&self.time - hold_time) > self.distance
+    }
+
+    // Same as Race::winning_interval for values past u64
+    fn winning_interval(&self) -> Option<(BigUint, BigUint)> {
+        let one = BigUint::one();
+        if !self.wins(&(&self.time / 2u32)) {
+            return None;
+        }
+        let root = (&self.time * &self.time - &self.distance * 4u32).sqrt();
+        let mut first = (&self.time - root) / 2u32;
+        while !self.wins(&first) {
+            first += &one;
+        }
+        while !first.is_zero() && self.wins(&(&first - &one)) {
+            first -= &one;
+        }
+        let last = &self.time - &first;
+        Some((first, last))
+    }
+
+    fn ways_to_win(&self) -> BigUint {
+        match self.winning_interval() {
+            Some((first, last)) => last - first + 1u32,
+            None => BigUint::zero(),
+        }
+    }
+
+    // Distance peaks at time / 2, on both middle values when time is odd
+    fn optimal_hold_times(&self) -> Vec<BigUint> {
+        let mut hold_times = vec![&self.time / 2u32, (&self.time + 1u32) / 2u32];
+        hold_times.dedup();
+        hold_times
+    }
+
+    fn max_distance(&self) -> BigUint {
+        let hold_time = &self.time / 2u32;
+        &hold_time * (&self.time - &hold_time)
+    }
+
+    fn margin(&self) -> BigInt {
+        BigInt::from(self.max_distance()) - BigInt::from(self.distance.clone())
+    }
+
+    // The plot is only drawn while the race fits in u64
+    fn report(&self) -> String {
+        let plot = match self.to_u64() {
+            Some(race) => race.plot(60, 12),
+            None => "plot: not available past u64\n".to_string(),
+        };
+        let optimal: Vec<String> = self
+            .optimal_hold_times()
+            .iter()
+            .map(BigUint::to_string)
+            .collect();
+        let interval = match self.winning_interval() {
+            Some((first, last)) => format!("{}..={}", first, last),
+            None => "none".to_string(),
+        };
+        format!(
+            "time {}, record {}\n\
+             optimal hold time: {}\n\
+             max distance: {}\n\
+             margin over record: {}\n\
+             winning hold times: {} ({} ways)\n\
+             {}",
+            self.time,
+            self.distance,
+            optimal.join(", "),
+            self.max_distance(),
+            self.margin(),
+            interval,
+            self.ways_to_win(),
+            plot
+        )
+    }
+
+    fn to_u64(&self) -> Option<Race> {
+        Some(Race {
+            time: self.time.to_u64()?,
+            distance: self.distance.to_u64()?,
         })
-        .map(|n| n.parse::<u64>().unwrap())
-        .collect::<Vec<u64>>();
-    Race {
-        time: *num.get(0).unwrap(),
-        distance: *num.get(1).unwrap(),
+    }
+
+    // Uses u64 math whenever the race fits in it
+    fn solve(&self) -> BigUint {
+        match self.to_u64() {
+            Some(race) => BigUint::from(race.ways_to_win()),
+            None => self.ways_to_win(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ParseError {
+    MissingLine(&'static str),
+    InvalidNumber { line: usize, value: String },
+    ColumnMismatch { times: usize, distances: usize },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingLine(name) => write!(f, "missing '{}:' line", name),
+            Self::InvalidNumber { line, value } => {
+                write!(f, "line {}: invalid number '{}'", line, value)
+            }
+            Self::ColumnMismatch { times, distances } => write!(
+                f,
+                "{} times but {} distances, every race needs both",
+                times, distances
+            ),
+        }
     }
 }
 
-fn part_1() -> u64 {
-    let races = parse_part_1();
-    races
+// Numbers following "name:", or all digits joined into one number when the
+// sheet is read with kerning
+fn parse_row(
+    lines: &[String],
+    name: &'static str,
+    kerning: bool,
+) -> Result<Vec<BigUint>, ParseError> {
+    let (line, values) = lines
+        .iter()
+        .enumerate()
+        .find_map(|(i, l)| {
+            let (label, values) = l.split_once(':')?;
+            (label.trim() == name).then_some((i + 1, values))
+        })
+        .ok_or(ParseError::MissingLine(name))?;
+    let values: Vec<String> = match kerning {
+        true => vec![values.split_whitespace().collect::<String>()],
+        false => values.split_whitespace().map(str::to_string).collect(),
+    };
+    values
         .into_iter()
-        .map(|f| {
-            (0..=f.time)
-                .into_iter()
-                .map(|hold_speed| {
-                    let distance = (f.time - hold_speed) * hold_speed;
-                    (distance, hold_speed)
-                })
-                .filter(|(distance, _)| distance > &f.distance)
-                .map(|(_, speed)| speed)
-                .count() as u64
+        .filter(|v| !v.is_empty())
+        .map(|v| {
+            v.parse::<BigUint>()
+                .map_err(|_| ParseError::InvalidNumber { line, value: v })
         })
-        .product()
+        .collect()
 }
 
-fn part_2() -> u64 {
-    let race = parse_part_2();
-    let mut counter = 0;
-    for hold_speed in 0..=race.time {
-        let distance = (race.time - hold_speed) * hold_speed;
-        if distance > race.distance {
-            counter += 1;
-        }
+fn parse_races(lines: &[String], kerning: bool) -> Result<Vec<Race<BigUint>>, ParseError> {
+    let times = parse_row(lines, "Time", kerning)?;
+    let distances = parse_row(lines, "Distance", kerning)?;
+    if times.len() != distances.len() {
+        return Err(ParseError::ColumnMismatch {
+            times: times.len(),
+            distances: distances.len(),
+        });
     }
-    counter
+    Ok(times
+        .into_iter()
+        .zip(distances)
+        .map(|(time, distance)| Race { time, distance })
+        .collect())
+}
+
+fn parse(kerning: bool) -> Vec<Race<BigUint>> {
+    let file = File::open("input.txt").expect("not found");
+    let reader = BufReader::new(file);
+    let lines: Vec<String> = reader.lines().map(Result::unwrap).collect();
+    parse_races(&lines, kerning).unwrap_or_else(|e| {
+        eprintln!("input.txt: {}", e);
+        process::exit(1);
+    })
+}
+
+// Both parts, they only differ in whether the sheet is read with kerning
+fn ways_product(races: &[Race<BigUint>]) -> BigUint {
+    races.iter().map(Race::solve).product()
 }
 
 fn main() {
@@ -95,8 +317,291 @@ fn main() {
     };
 
     if part == "part1" {
-        println!("{}", part_1());
+        println!("{}", ways_product(&parse(false)));
     } else if part == "part2" {
-        println!("{}", part_2());
+        println!("{}", ways_product(&parse(true)));
+    } else if part == "report" {
+        let kerning = env::var("kerning").is_ok_and(|k| k == "1" || k == "true");
+        for race in parse(kerning) {
+            println!("{}", race.report());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brute_force(race: &Race) -> u64 {
+        (0..=race.time)
+            .filter(|hold_time| (race.time - hold_time) * hold_time > race.distance)
+            .count() as u64
+    }
+
+    #[test]
+    fn example_races() {
+        let races = [
+            Race {
+                time: 7,
+                distance: 9,
+            },
+            Race {
+                time: 15,
+                distance: 40,
+            },
+            Race {
+                time: 30,
+                distance: 200,
+            },
+        ];
+        assert_eq!(
+            vec![4, 8, 9],
+            races
+                .iter()
+                .map(Race::<u64>::ways_to_win)
+                .collect::<Vec<u64>>()
+        );
+        let race = Race {
+            time: 71530,
+            distance: 940200,
+        };
+        assert_eq!(71503, race.ways_to_win());
+    }
+
+    #[test]
+    fn closed_form_matches_brute_force() {
+        for time in 0..80 {
+            for distance in 0..(time * time / 4 + 3) {
+                let race = Race { time, distance };
+                assert_eq!(brute_force(&race), race.ways_to_win());
+            }
+        }
+    }
+
+    #[test]
+    fn boundary_ties() {
+        // Holding 5ms goes exactly 25mm, which only ties the record
+        let race = Race {
+            time: 10,
+            distance: 25,
+        };
+        assert_eq!(0, race.ways_to_win());
+        // Holding 2ms or 8ms ties at 16mm, only 3..=7 win
+        let race = Race {
+            time: 10,
+            distance: 16,
+        };
+        assert_eq!(5, race.ways_to_win());
+    }
+
+    #[test]
+    fn exact_isqrt() {
+        for n in 0..10_000u128 {
+            let root = isqrt(n);
+            assert!(root * root <= n && (root + 1) * (root + 1) > n);
+        }
+        assert_eq!(u64::MAX as u128, isqrt(u128::MAX));
+        let big = (1u128 << 64) - 3;
+        assert_eq!(big - 1, isqrt(big * big - 1));
+        assert_eq!(big, isqrt(big * big));
+    }
+
+    #[test]
+    fn large_race() {
+        let race = Race {
+            time: u64::MAX,
+            distance: u64::MAX,
+        };
+        assert_eq!(u64::MAX - 3, race.ways_to_win());
+    }
+
+    fn big(n: &str) -> BigUint {
+        n.parse::<BigUint>().unwrap()
+    }
+
+    #[test]
+    fn big_matches_u64() {
+        for time in 0..60u64 {
+            for distance in 0..(time * time / 4 + 3) {
+                let race = Race { time, distance };
+                let big_race = Race {
+                    time: BigUint::from(time),
+                    distance: BigUint::from(distance),
+                };
+                assert_eq!(BigUint::from(race.ways_to_win()), big_race.ways_to_win());
+            }
+        }
+    }
+
+    #[test]
+    fn big_race() {
+        // Scaled up version of the part 2 example, roots stay 10^30 apart
+        let race = Race {
+            time: big("71530000000000000000000000000000"),
+            distance: big("940200000000000000000000000000000000000000000000000000000000"),
+        };
+        assert!(race.to_u64().is_none());
+        let ways = race.solve();
+        let first = (&race.time + BigUint::one() - &ways) / 2u32;
+        assert!(race.wins(&first));
+        assert!(!race.wins(&(&first - 1u32)));
+        assert!(race.wins(&(&race.time - &first)));
+        assert!(!race.wins(&(&race.time - &first + 1u32)));
+
+        let small = Race {
+            time: big("71530"),
+            distance: big("940200"),
+        };
+        assert_eq!(BigUint::from(71503u32), small.solve());
+    }
+
+    fn sheet(input: &str) -> Vec<String> {
+        input.lines().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_sheet() {
+        let lines = sheet("Time:      7  15   30\nDistance:  9  40  200");
+        let races = parse_races(&lines, false).unwrap();
+        assert_eq!(3, races.len());
+        assert_eq!(BigUint::from(288u32), ways_product(&races));
+
+        let races = parse_races(&lines, true).unwrap();
+        assert_eq!(1, races.len());
+        assert_eq!(big("71530"), races[0].time);
+        assert_eq!(big("940200"), races[0].distance);
+        assert_eq!(BigUint::from(71503u32), ways_product(&races));
+    }
+
+    #[test]
+    fn parse_sheet_whitespace() {
+        let lines = sheet("\n  Time :\t7   15  \n\n Distance:9 40   \n");
+        let races = parse_races(&lines, false).unwrap();
+        assert_eq!(
+            vec![(7u32, 9u32), (15, 40)],
+            races
+                .iter()
+                .map(|r| (r.time.to_u32().unwrap(), r.distance.to_u32().unwrap()))
+                .collect::<Vec<(u32, u32)>>()
+        );
+    }
+
+    #[test]
+    fn parse_sheet_errors() {
+        assert_eq!(
+            Err(ParseError::ColumnMismatch {
+                times: 3,
+                distances: 2
+            }),
+            parse_races(&sheet("Time: 7 15 30\nDistance: 9 40"), false).map(|_| ())
+        );
+        assert_eq!(
+            Err(ParseError::MissingLine("Distance")),
+            parse_races(&sheet("Time: 7 15 30"), false).map(|_| ())
+        );
+        assert_eq!(
+            Err(ParseError::InvalidNumber {
+                line: 2,
+                value: "4x".to_string()
+            }),
+            parse_races(&sheet("Time: 7 15\nDistance: 9 4x"), false).map(|_| ())
+        );
+        // Kerning joins the columns, so the counts always line up
+        assert!(parse_races(&sheet("Time: 7 15 30\nDistance: 9 40"), true).is_ok());
+    }
+
+    #[test]
+    fn race_analysis() {
+        let race = Race {
+            time: big("7"),
+            distance: big("9"),
+        };
+        assert_eq!(vec![big("3"), big("4")], race.optimal_hold_times());
+        assert_eq!(big("12"), race.max_distance());
+        assert_eq!(BigInt::from(3), race.margin());
+        assert_eq!(Some((big("2"), big("5"))), race.winning_interval());
+
+        let race = Race {
+            time: big("10"),
+            distance: big("30"),
+        };
+        assert_eq!(vec![big("5")], race.optimal_hold_times());
+        assert_eq!(big("25"), race.max_distance());
+        assert_eq!(BigInt::from(-5), race.margin());
+        assert_eq!(None, race.winning_interval());
+    }
+
+    #[test]
+    fn race_plot() {
+        let race = Race {
+            time: 7,
+            distance: 9,
+        };
+        let expected = "\
+12 |   ##   
+   |  #  #  
+ 9 |--------
+   | o    o 
+   |        
+   |        
+ 0 |o      o
+   +--------
+    0      7
+";
+        assert_eq!(expected, race.plot(60, 7));
+
+        // No column lands on the peak at 500, the top row still has one
+        let race = Race {
+            time: 1000,
+            distance: 200000,
+        };
+        let plot = race.plot(60, 12);
+        assert!(plot.lines().next().unwrap().contains('#'));
+    }
+
+    #[test]
+    fn big_race_analysis() {
+        let race = Race {
+            time: big("100000000000000000001"),
+            distance: big("900000000000000000000000000000000000000"),
+        };
+        assert!(race.to_u64().is_none());
+        assert_eq!(
+            vec![big("50000000000000000000"), big("50000000000000000001")],
+            race.optimal_hold_times()
+        );
+        assert_eq!(
+            big("2500000000000000000050000000000000000000"),
+            race.max_distance()
+        );
+        assert_eq!(
+            "1600000000000000000050000000000000000000",
+            race.margin().to_string()
+        );
+        let (first, last) = race.winning_interval().unwrap();
+        assert_eq!(&race.time - &first, last);
+        assert!(race.wins(&first) && !race.wins(&(&first - 1u32)));
+        assert!(race.report().contains("plot: not available past u64"));
+
+        let race = Race {
+            time: big("100000000000000000000"),
+            distance: big("2500000000000000000000000000000000000000"),
+        };
+        assert_eq!(None, race.winning_interval());
+        assert_eq!(BigInt::zero(), race.margin());
+    }
+
+    #[test]
+    fn plot_limits() {
+        let race = Race {
+            time: BigUint::from(u64::MAX),
+            distance: BigUint::from(u64::MAX),
+        };
+        assert!(race.report().contains(&format!("0{:>59}", u64::MAX)));
+        let race = race.to_u64().unwrap();
+        let plot = race.plot(60, 12);
+        assert_eq!(12 + 2, plot.lines().count());
+        assert!(plot.lines().nth(1).unwrap().contains("##"));
+        assert_eq!(3, race.plot(0, 0).lines().count());
     }
 }
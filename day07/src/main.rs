@@ -1,97 +1,287 @@
+use parse_display::Display;
+use std::cmp::Ordering;
 use std::env;
+use std::fmt;
 use std::fs::File;
+use std::io;
 use std::io::BufRead;
 use std::io::BufReader;
-use std::vec;
-
-use strum::IntoEnumIterator;
-use strum_macros::Display;
-use strum_macros::EnumIter;
-
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, EnumIter, Display, Clone, Copy)]
-enum CardRank {
-    _2,
-    _3,
-    _4,
-    _5,
-    _6,
-    _7,
-    _8,
-    _9,
-    T,
-    J,
-    Q,
-    K,
-    A,
-}
+use std::process;
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Display, Clone, Copy)]
+// Card by its position in the ruleset's rank order. Wildcards are weaker
+// than any other card when breaking ties.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+enum Card {
+    Wild(u8),
+    Rank(u8),
+}
 
-enum ExtCardRank {
-    J,
-    Base(CardRank),
+#[derive(Debug, PartialEq)]
+enum ParseError {
+    UnknownCard(char),
+    InvalidCard(String),
+    UnknownSuit(char),
+    DuplicateCard(String),
+    HandSize { expected: usize, found: usize },
+    MissingBid,
+    InvalidBid(String),
+    NegativeBid(String),
+    UnexpectedField(String),
 }
 
-impl ExtCardRank {
-    fn from_char(c: &char) -> ExtCardRank {
-        match c {
-            'J' => ExtCardRank::J,
-            _ => CardRank::from_char(c),
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownCard(c) => write!(f, "unknown card '{}'", c),
+            Self::InvalidCard(s) => write!(f, "invalid card '{}', expected rank and suit", s),
+            Self::UnknownSuit(c) => write!(f, "unknown suit '{}'", c),
+            Self::DuplicateCard(s) => write!(f, "card {} is dealt twice", s),
+            Self::HandSize { expected, found } => {
+                write!(f, "expected {} cards but found {}", expected, found)
+            }
+            Self::MissingBid => write!(f, "missing bid after the cards"),
+            Self::InvalidBid(s) => write!(f, "invalid bid '{}'", s),
+            Self::NegativeBid(s) => write!(f, "bid {} is negative", s),
+            Self::UnexpectedField(s) => write!(f, "unexpected '{}' after the bid", s),
         }
     }
 }
 
-impl CardRank {
-    fn from_char(c: &char) -> ExtCardRank {
-        ExtCardRank::Base(match c {
-            '2' => Self::_2,
-            '3' => Self::_3,
-            '4' => Self::_4,
-            '5' => Self::_5,
-            '6' => Self::_6,
-            '7' => Self::_7,
-            '8' => Self::_8,
-            '9' => Self::_9,
-            'T' => Self::T,
-            'J' => Self::J,
-            'Q' => Self::Q,
-            'K' => Self::K,
-            'A' => Self::A,
-            _ => Self::A,
-        })
-    }
+fn parse_bid(bid: Option<&str>) -> Result<u64, ParseError> {
+    let bid = bid.ok_or(ParseError::MissingBid)?;
+    bid.parse::<u64>().map_err(|_| {
+        if bid.starts_with('-') && bid[1..].parse::<u64>().is_ok() {
+            ParseError::NegativeBid(bid.to_string())
+        } else {
+            ParseError::InvalidBid(bid.to_string())
+        }
+    })
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Display, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 enum HandType {
+    #[display("high card")]
     HighCard,
+    #[display("one pair")]
     OnePair,
+    #[display("two pair")]
     TwoPair,
+    #[display("three of a kind")]
     ThreeOfAKind,
+    #[display("straight")]
+    Straight,
+    #[display("flush")]
+    Flush,
+    #[display("full house")]
     FullHouse,
+    #[display("four of a kind")]
     FourOfAKind,
+    #[display("straight flush")]
+    StraightFlush,
+    #[display("five of a kind")]
     FiveOfAKind,
 }
 
 impl HandType {
-    fn from_card_ranks(cards: &Vec<ExtCardRank>) -> HandType {
-        let mut dup = cards.clone();
-        dup.sort();
-        dup.dedup();
-        let unique_c_count = dup.iter().count();
-        let dup_count: usize = cards
+    // Classifies by group sizes, largest first: [3, 2] is a full house,
+    // [2, 2, 1] two pair and so on
+    fn from_histogram(counts: &[usize]) -> HandType {
+        match counts {
+            [c, ..] if *c >= 5 => Self::FiveOfAKind,
+            [4, ..] => Self::FourOfAKind,
+            [3, c, ..] if *c >= 2 => Self::FullHouse,
+            [3, ..] => Self::ThreeOfAKind,
+            [2, 2, ..] => Self::TwoPair,
+            [2, ..] => Self::OnePair,
+            _ => Self::HighCard,
+        }
+    }
+
+    // Wildcards are best spent joining the largest group of the other cards
+    fn from_cards_with_wildcards(cards: &[Card]) -> HandType {
+        let others: Vec<&Card> = cards
             .iter()
-            .map(|c| cards.iter().filter(|c2| c2 == &c).count() - 1)
-            .sum();
+            .filter(|c| matches!(c, Card::Rank(_)))
+            .collect();
+        let wildcards = cards.len() - others.len();
+        let mut counts = histogram(&others);
+        match counts.first_mut() {
+            Some(largest) => *largest += wildcards,
+            None => counts.push(wildcards),
+        }
+        HandType::from_histogram(&counts)
+    }
+}
 
-        match (unique_c_count, dup_count) {
-            (2, 12) => Self::FourOfAKind,
-            (1, _) => Self::FiveOfAKind,
-            (2, _) => Self::FullHouse,
-            (3, 4) => Self::TwoPair,
-            (3, _) => Self::ThreeOfAKind,
-            (4, _) => Self::OnePair,
-            (_, _) => Self::HighCard,
+// Number of cards of each rank, largest group first
+fn histogram<C: Ord>(cards: &[C]) -> Vec<usize> {
+    let mut sorted: Vec<&C> = cards.iter().collect();
+    sorted.sort();
+    let mut counts: Vec<usize> = vec![];
+    for (i, c) in sorted.iter().enumerate() {
+        match counts.last_mut() {
+            Some(n) if sorted[i - 1] == *c => *n += 1,
+            _ => counts.push(1),
+        }
+    }
+    counts.sort_by(|a, b| b.cmp(a));
+    counts
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum TieBreak {
+    // Compare cards in the order they were dealt, as in Camel Cards
+    Dealt,
+    // Compare the largest groups first and higher ranks within them, like
+    // kickers in poker
+    Grouped,
+}
+
+#[derive(Debug, PartialEq)]
+struct Ruleset {
+    // Weakest rank first
+    ranks: Vec<char>,
+    wildcards: Vec<char>,
+    hand_size: usize,
+    tie_break: TieBreak,
+}
+
+impl Ruleset {
+    fn camel() -> Ruleset {
+        Ruleset {
+            ranks: "23456789TJQKA".chars().collect(),
+            wildcards: vec![],
+            hand_size: 5,
+            tie_break: TieBreak::Dealt,
+        }
+    }
+
+    fn camel_jokers() -> Ruleset {
+        Ruleset {
+            wildcards: vec!['J'],
+            ..Ruleset::camel()
+        }
+    }
+
+    // Changes the base rules by a description like
+    // "ranks=23456789TJQKA;wild=J2;size=6;tiebreak=grouped"
+    fn from_spec(base: Ruleset, spec: &str) -> Result<Ruleset, String> {
+        let mut rules = base;
+        for setting in spec.split(';').filter(|s| !s.trim().is_empty()) {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or(format!("expected key=value, got '{}'", setting))?;
+            let value = value.trim();
+            match key.trim() {
+                "ranks" => rules.ranks = value.chars().collect(),
+                "wild" => rules.wildcards = value.chars().collect(),
+                "size" => {
+                    rules.hand_size = value
+                        .parse::<usize>()
+                        .map_err(|_| format!("invalid hand size '{}'", value))?
+                }
+                "tiebreak" => {
+                    rules.tie_break = match value {
+                        "dealt" => TieBreak::Dealt,
+                        "grouped" => TieBreak::Grouped,
+                        _ => return Err(format!("unknown tie-break '{}'", value)),
+                    }
+                }
+                _ => return Err(format!("unknown rule '{}'", key)),
+            }
+        }
+
+        if rules.ranks.is_empty() || rules.ranks.len() > u8::MAX as usize {
+            return Err(format!("need 1 to {} ranks", u8::MAX));
+        }
+        if let Some(c) = rules
+            .ranks
+            .iter()
+            .enumerate()
+            .find(|(i, c)| rules.ranks[..*i].contains(c))
+        {
+            return Err(format!("rank '{}' is listed twice", c.1));
+        }
+        if let Some(c) = rules.wildcards.iter().find(|c| !rules.ranks.contains(c)) {
+            return Err(format!("wildcard '{}' is not a rank", c));
+        }
+        if rules.hand_size == 0 {
+            return Err("hand size must be at least 1".to_string());
+        }
+        Ok(rules)
+    }
+
+    fn symbol(&self, card: Card) -> char {
+        match card {
+            Card::Wild(rank) | Card::Rank(rank) => self.ranks[rank as usize],
+        }
+    }
+
+    fn card(&self, c: char) -> Option<Card> {
+        let rank = self.ranks.iter().position(|r| *r == c)? as u8;
+        if self.wildcards.contains(&c) {
+            Some(Card::Wild(rank))
+        } else {
+            Some(Card::Rank(rank))
+        }
+    }
+
+    fn cards(&self, hand: &str) -> Result<Vec<Card>, ParseError> {
+        let cards = hand
+            .chars()
+            .map(|c| self.card(c).ok_or(ParseError::UnknownCard(c)))
+            .collect::<Result<Vec<Card>, ParseError>>()?;
+        self.check_size(cards)
+    }
+
+    fn check_size(&self, cards: Vec<Card>) -> Result<Vec<Card>, ParseError> {
+        if cards.len() != self.hand_size {
+            return Err(ParseError::HandSize {
+                expected: self.hand_size,
+                found: cards.len(),
+            });
+        }
+        Ok(cards)
+    }
+
+    fn hand_type(&self, cards: &[Card]) -> HandType {
+        HandType::from_cards_with_wildcards(cards)
+    }
+
+    // Cards in the order they are compared when hand types are equal
+    fn tie_break_order(&self, cards: &[Card]) -> Vec<Card> {
+        match self.tie_break {
+            TieBreak::Dealt => cards.to_vec(),
+            TieBreak::Grouped => {
+                let mut sorted = cards.to_vec();
+                let count = |c: &Card| cards.iter().filter(|o| *o == c).count();
+                sorted.sort_by(|a, b| count(b).cmp(&count(a)).then(b.cmp(a)));
+                sorted
+            }
+        }
+    }
+
+    fn hand(&self, line: &str) -> Result<Hand, ParseError> {
+        let mut fields = line.split_whitespace();
+        let dealt = fields.next().unwrap_or("");
+        let cards = self.cards(dealt)?;
+        let bid = parse_bid(fields.next())?;
+        if let Some(field) = fields.next() {
+            return Err(ParseError::UnexpectedField(field.to_string()));
+        }
+        Ok(self.scored(cards, dealt, bid))
+    }
+
+    fn deal(&self, dealt: &str, bid: u64) -> Result<Hand, ParseError> {
+        Ok(self.scored(self.cards(dealt)?, dealt, bid))
+    }
+
+    fn scored(&self, cards: Vec<Card>, dealt: &str, bid: u64) -> Hand {
+        Hand {
+            hand_type: self.hand_type(&cards),
+            hand: self.tie_break_order(&cards),
+            bid,
+            dealt: dealt.to_string(),
         }
     }
 }
@@ -99,39 +289,140 @@ impl HandType {
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 struct Hand {
     hand_type: HandType,
-    hand: Vec<ExtCardRank>,
+    hand: Vec<Card>,
     bid: u64,
+    // Cards as written in the input
+    dealt: String,
+}
+
+// Standard poker with hands written as rank and suit, like "AS KD 7H 7C 2S 765"
+struct Poker {
+    rules: Ruleset,
+}
+
+impl Poker {
+    fn new() -> Poker {
+        Poker {
+            rules: Ruleset {
+                tie_break: TieBreak::Grouped,
+                ..Ruleset::camel()
+            },
+        }
+    }
+
+    // A card is its rank followed by one of the suits C, D, H or S
+    fn card(&self, field: &str) -> Result<(Card, char), ParseError> {
+        let chars: Vec<char> = field.chars().collect();
+        let [rank, suit] = chars[..] else {
+            return Err(ParseError::InvalidCard(field.to_string()));
+        };
+        let card = self.rules.card(rank).ok_or(ParseError::UnknownCard(rank))?;
+        if !"CDHS".contains(suit) {
+            return Err(ParseError::UnknownSuit(suit));
+        }
+        Ok((card, suit))
+    }
+
+    fn hand(&self, line: &str) -> Result<Hand, ParseError> {
+        let mut fields: Vec<&str> = line.split_whitespace().collect();
+        // The bid is left off if the last field is a card
+        let bid = match fields.last() {
+            Some(f) if self.card(f).is_err() => fields.pop(),
+            _ => None,
+        };
+        let (cards, suits): (Vec<Card>, Vec<char>) = fields
+            .iter()
+            .map(|f| self.card(f))
+            .collect::<Result<Vec<(Card, char)>, ParseError>>()?
+            .into_iter()
+            .unzip();
+        // A deck has one of each rank and suit
+        if let Some(i) = (0..cards.len())
+            .find(|i| (0..*i).any(|j| cards[j] == cards[*i] && suits[j] == suits[*i]))
+        {
+            return Err(ParseError::DuplicateCard(fields[i].to_string()));
+        }
+        let cards = self.rules.check_size(cards)?;
+        let bid = parse_bid(bid)?;
+
+        let mut order = self.rules.tie_break_order(&cards);
+        let flush = suits.len() == cards.len() && suits.iter().all(|s| *s == suits[0]);
+        let straight = self.is_straight(&mut order);
+        let hand_type = match (straight, flush) {
+            (true, true) => HandType::StraightFlush,
+            (true, false) => HandType::Straight,
+            (false, true) => HandType::Flush,
+            _ => HandType::HighCard,
+        }
+        .max(self.rules.hand_type(&cards));
+        Ok(Hand {
+            hand_type,
+            hand: order,
+            bid,
+            dealt: fields.join(" "),
+        })
+    }
+
+    // Takes the cards highest first, the ace counts low in A-2-3-4-5 and is
+    // moved last so the straight ranks as five high
+    fn is_straight(&self, order: &mut [Card]) -> bool {
+        if order.len() != 5 || order.windows(2).any(|w| w[0] == w[1]) {
+            return false;
+        }
+        let card = |c: char| self.rules.card(c).unwrap();
+        if order[0] == card('A') && order[1] == card('5') && order[4] == card('2') {
+            order.rotate_left(1);
+            return true;
+        }
+        match (order[0], order[4]) {
+            (Card::Rank(high), Card::Rank(low)) => high - low == 4,
+            _ => false,
+        }
+    }
 }
 
 fn parse_to_string() -> Vec<String> {
-    let file = File::open("input.txt").expect("not found");
+    let input = env::var("input").unwrap_or("input.txt".to_string());
+    let file = File::open(input).expect("not found");
     let reader = BufReader::new(file);
     reader.lines().map(Result::unwrap).collect::<Vec<String>>()
 }
 
-fn parse(card_fun: fn(c: &char) -> ExtCardRank) -> Vec<Hand> {
-    parse_to_string()
+// Blank lines are skipped, errors carry the line number
+fn parse_hands<F>(lines: &[String], hand: F) -> Result<Vec<Hand>, (usize, ParseError)>
+where
+    F: Fn(&str) -> Result<Hand, ParseError>,
+{
+    lines
         .iter()
-        .map(|f| {
-            let chars: Vec<char> = f.chars().collect();
-            let hand = chars[0..5].iter().map(|c| card_fun(&c)).collect();
-            Hand {
-                hand_type: HandType::from_card_ranks(&hand),
-                hand,
-                bid: f
-                    .split_once(char::is_whitespace)
-                    .unwrap()
-                    .1
-                    .parse::<u64>()
-                    .ok()
-                    .unwrap(),
-            }
-        })
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(line_no, l)| hand(l).map_err(|e| (line_no + 1, e)))
         .collect()
 }
 
-fn part_1() -> u64 {
-    let mut hand = parse(CardRank::from_char);
+fn parse<F>(hand: F) -> Vec<Hand>
+where
+    F: Fn(&str) -> Result<Hand, ParseError>,
+{
+    let input = env::var("input").unwrap_or("input.txt".to_string());
+    parse_or_exit(&input, &parse_to_string(), hand)
+}
+
+fn parse_or_exit<F>(source: &str, lines: &[String], hand: F) -> Vec<Hand>
+where
+    F: Fn(&str) -> Result<Hand, ParseError>,
+{
+    match parse_hands(lines, hand) {
+        Ok(hands) => hands,
+        Err((line_no, e)) => {
+            eprintln!("{}:{}: {}", source, line_no, e);
+            process::exit(1);
+        }
+    }
+}
+
+fn winnings(mut hand: Vec<Hand>) -> u64 {
     hand.sort();
     hand.iter()
         .enumerate()
@@ -139,52 +430,227 @@ fn part_1() -> u64 {
         .sum()
 }
 
-fn part_2() -> u64 {
-    let mut hand = parse(ExtCardRank::from_char);
-    for h in hand.iter_mut() {
-        let mut jokerhands: Vec<Hand> = vec![];
-        CardRank::iter().for_each(|c1| {
-            let jokercards = h
-                .hand
-                .iter()
-                .map(|c2| {
-                    if c2 == &ExtCardRank::J {
-                        ExtCardRank::Base(c1.to_owned())
-                    } else {
-                        c2.to_owned()
-                    }
-                })
-                .collect::<Vec<ExtCardRank>>();
-            let hand = Hand {
-                hand_type: HandType::from_card_ranks(&jokercards),
-                hand: jokercards,
-                bid: h.bid,
+#[derive(Debug, PartialEq)]
+enum Decider {
+    // The hand types differ
+    HandType,
+    // Same hand type, decided by the cards at this position of the tie-break
+    // order, counting from 0
+    Card(usize),
+    Tie,
+}
+
+fn decide(a: &Hand, b: &Hand) -> (Ordering, Decider) {
+    if a.hand_type != b.hand_type {
+        return (a.hand_type.cmp(&b.hand_type), Decider::HandType);
+    }
+    match a.hand.iter().zip(&b.hand).position(|(x, y)| x != y) {
+        Some(i) => (a.hand[i].cmp(&b.hand[i]), Decider::Card(i)),
+        None => (Ordering::Equal, Decider::Tie),
+    }
+}
+
+fn explain(rules: &Ruleset, a: &Hand, b: &Hand) -> String {
+    let (ordering, decider) = decide(a, b);
+    let (winner, loser) = match ordering {
+        Ordering::Less => (b, a),
+        _ => (a, b),
+    };
+    match decider {
+        Decider::HandType => format!(
+            "{} wins: {} beats {}",
+            winner.dealt, winner.hand_type, loser.hand_type
+        ),
+        Decider::Card(i) => {
+            let position = match rules.tie_break {
+                TieBreak::Dealt => "card",
+                TieBreak::Grouped => "grouped card",
             };
-            jokerhands.push(hand);
-        });
-        jokerhands.sort();
+            format!(
+                "{} wins: both {}, {} {} is {} against {}",
+                winner.dealt,
+                winner.hand_type,
+                position,
+                i + 1,
+                rules.symbol(winner.hand[i]),
+                rules.symbol(loser.hand[i])
+            )
+        }
+        Decider::Tie => format!("{} ties with {}: same type and cards", a.dealt, b.dealt),
+    }
+}
 
-        let jokerhand = jokerhands.last().unwrap();
-        h.hand_type = jokerhand.hand_type.clone();
+fn compare(rules: &Ruleset, args: &[String]) {
+    let [a, b] = args else {
+        eprintln!("usage: compare <hand> <hand> [--jokers]");
+        process::exit(1);
+    };
+    let deal = |cards: &str| {
+        rules.deal(cards, 0).unwrap_or_else(|e| {
+            eprintln!("{}: {}", cards, e);
+            process::exit(1);
+        })
+    };
+    let (a, b) = (deal(a), deal(b));
+    println!("{}: {}", a.dealt, a.hand_type);
+    println!("{}: {}", b.dealt, b.hand_type);
+    println!("{}", explain(rules, &a, &b));
+}
+
+// Number of hands and total bid of each hand type that occurs, weakest first
+fn type_stats(hands: &[Hand]) -> Vec<(HandType, usize, u64)> {
+    let mut stats: Vec<(HandType, usize, u64)> = vec![];
+    for h in hands {
+        match stats.iter_mut().find(|(t, _, _)| *t == h.hand_type) {
+            Some((_, n, bids)) => {
+                *n += 1;
+                *bids += h.bid
+            }
+            None => stats.push((h.hand_type.clone(), 1, h.bid)),
+        }
     }
+    stats.sort();
+    stats
+}
 
-    hand.sort();
-    hand.iter()
-        .enumerate()
-        .map(|(i, h)| h.bid * (i + 1) as u64)
-        .sum()
+// Hands whose type is better with jokers, given the same hands in the same
+// order with and without them
+fn promotions<'a>(plain: &'a [Hand], jokers: &'a [Hand]) -> Vec<(&'a Hand, &'a HandType)> {
+    plain
+        .iter()
+        .zip(jokers)
+        .filter(|(p, j)| j.hand_type > p.hand_type)
+        .map(|(p, j)| (p, &j.hand_type))
+        .collect()
+}
+
+fn stats(plain: &[Hand], jokers: &[Hand]) {
+    let (plain_stats, joker_stats) = (type_stats(plain), type_stats(jokers));
+    let mut types: Vec<&HandType> = plain_stats
+        .iter()
+        .chain(&joker_stats)
+        .map(|(t, _, _)| t)
+        .collect();
+    types.sort();
+    types.dedup();
+
+    let columns = |stats: &[(HandType, usize, u64)], t: &HandType| match stats
+        .iter()
+        .find(|(s, _, _)| s == t)
+    {
+        Some((_, n, bids)) => format!("{:>6}  {:>8.1}", n, *bids as f64 / *n as f64),
+        None => format!("{:>6}  {:>8}", 0, "-"),
+    };
+    println!(
+        "{:<15}  {:>6}  {:>8}  {:>6}  {:>8}",
+        "type", "hands", "avg bid", "jokers", "avg bid"
+    );
+    for t in types {
+        println!(
+            "{:<15}  {}  {}",
+            t.to_string(),
+            columns(&plain_stats, t),
+            columns(&joker_stats, t)
+        );
+    }
+
+    let promoted = promotions(plain, jokers);
+    let mut moves: Vec<((&HandType, &HandType), usize)> = vec![];
+    for (h, to) in &promoted {
+        match moves.iter_mut().find(|(m, _)| *m == (&h.hand_type, *to)) {
+            Some((_, n)) => *n += 1,
+            None => moves.push(((&h.hand_type, to), 1)),
+        }
+    }
+    moves.sort();
+    println!();
+    println!("{} hands promoted by jokers", promoted.len());
+    for ((from, to), n) in moves {
+        println!("{:>6}  {} -> {}", n, from, to);
+    }
+    println!();
+    for (h, to) in promoted {
+        println!("{}  {} -> {}", h.dealt, h.hand_type, to);
+    }
+}
+
+// Ranks the hands read from stdin, weakest first
+fn rank(rules: &Ruleset) {
+    let lines: Vec<String> = io::stdin().lock().lines().map(Result::unwrap).collect();
+    let mut hands = parse_or_exit("stdin", &lines, |l| rules.hand(l));
+    hands.sort();
+    println!(
+        "{:>5}  {:<width$}  {:<15}  {:>6}  {:>10}",
+        "rank",
+        "hand",
+        "type",
+        "bid",
+        "winnings",
+        width = rules.hand_size
+    );
+    for (i, h) in hands.iter().enumerate() {
+        println!(
+            "{:>5}  {:<width$}  {:<15}  {:>6}  {:>10}",
+            i + 1,
+            h.dealt,
+            h.hand_type.to_string(),
+            h.bid,
+            h.bid * (i + 1) as u64,
+            width = rules.hand_size
+        );
+    }
+    println!("total winnings: {}", winnings(hands));
+}
+
+// Both parts, part 2 plays with jokers
+fn total_winnings(rules: &Ruleset) -> u64 {
+    winnings(parse(|l| rules.hand(l)))
+}
+
+// Rules from the rules variable on top of the default for the part
+fn ruleset(default: Ruleset) -> Ruleset {
+    match env::var("rules") {
+        Ok(spec) => Ruleset::from_spec(default, &spec).unwrap_or_else(|e| {
+            eprintln!("rules: {}", e);
+            process::exit(1);
+        }),
+        Err(_e) => default,
+    }
 }
 
 fn main() {
-    let part = match env::var("part") {
-        Ok(val) => val,
-        Err(_e) => "part1".to_string(),
+    // A compare or rank command on the command line takes precedence
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let jokers = args.iter().any(|a| a == "--jokers");
+    args.retain(|a| a != "--jokers");
+    let part = match (args.first(), env::var("part")) {
+        (Some(command), _) => command.clone(),
+        (None, Ok(val)) => val,
+        (None, Err(_e)) => "part1".to_string(),
+    };
+    let rules = || {
+        ruleset(if jokers {
+            Ruleset::camel_jokers()
+        } else {
+            Ruleset::camel()
+        })
     };
 
     if part == "part1" {
-        println!("{}", part_1());
+        println!("{}", total_winnings(&ruleset(Ruleset::camel())));
     } else if part == "part2" {
-        println!("{}", part_2());
+        println!("{}", total_winnings(&ruleset(Ruleset::camel_jokers())));
+    } else if part == "poker" {
+        let poker = Poker::new();
+        println!("{}", winnings(parse(|l| poker.hand(l))));
+    } else if part == "stats" {
+        let plain = ruleset(Ruleset::camel());
+        let jokers = ruleset(Ruleset::camel_jokers());
+        stats(&parse(|l| plain.hand(l)), &parse(|l| jokers.hand(l)));
+    } else if part == "compare" {
+        compare(&rules(), args.get(1..).unwrap_or(&[]));
+    } else if part == "rank" {
+        rank(&rules());
     }
 }
 
@@ -192,94 +658,447 @@ fn main() {
 mod tests {
     use super::*;
 
+    fn hand_type(cards: &str) -> HandType {
+        let rules = Ruleset::camel();
+        rules.hand_type(&rules.cards(cards).unwrap())
+    }
+
     #[test]
     fn parse_highcard() {
-        let cards = vec![
-            ExtCardRank::Base(CardRank::A),
-            ExtCardRank::Base(CardRank::T),
-            ExtCardRank::Base(CardRank::J),
-            ExtCardRank::Base(CardRank::Q),
-            ExtCardRank::Base(CardRank::K),
-        ];
-        let expected = HandType::HighCard;
-        assert_eq!(expected, HandType::from_card_ranks(&cards));
+        assert_eq!(HandType::HighCard, hand_type("ATJQK"));
     }
 
     #[test]
     fn parse_five_of_a_kind() {
-        let cards = vec![
-            ExtCardRank::Base(CardRank::A),
-            ExtCardRank::Base(CardRank::A),
-            ExtCardRank::Base(CardRank::A),
-            ExtCardRank::Base(CardRank::A),
-            ExtCardRank::Base(CardRank::A),
-        ];
-        let expected = HandType::FiveOfAKind;
-        assert_eq!(expected, HandType::from_card_ranks(&cards));
+        assert_eq!(HandType::FiveOfAKind, hand_type("AAAAA"));
     }
 
     #[test]
     fn parse_one_pair() {
-        let cards = vec![
-            ExtCardRank::Base(CardRank::A),
-            ExtCardRank::Base(CardRank::_2),
-            ExtCardRank::Base(CardRank::_3),
-            ExtCardRank::Base(CardRank::A),
-            ExtCardRank::Base(CardRank::_4),
-        ];
-        let expected = HandType::OnePair;
-        assert_eq!(expected, HandType::from_card_ranks(&cards));
+        assert_eq!(HandType::OnePair, hand_type("A23A4"));
     }
 
     #[test]
     fn parse_two_pair() {
-        let cards = vec![
-            ExtCardRank::Base(CardRank::_2),
-            ExtCardRank::Base(CardRank::_3),
-            ExtCardRank::Base(CardRank::_4),
-            ExtCardRank::Base(CardRank::_3),
-            ExtCardRank::Base(CardRank::_2),
-        ];
-        let expected = HandType::TwoPair;
-        assert_eq!(expected, HandType::from_card_ranks(&cards));
+        assert_eq!(HandType::TwoPair, hand_type("23432"));
     }
 
     #[test]
     fn parse_three_of_a_kind() {
-        let cards = vec![
-            ExtCardRank::Base(CardRank::T),
-            ExtCardRank::Base(CardRank::T),
-            ExtCardRank::Base(CardRank::T),
-            ExtCardRank::Base(CardRank::_9),
-            ExtCardRank::Base(CardRank::_8),
-        ];
-        let expected = HandType::ThreeOfAKind;
-        assert_eq!(expected, HandType::from_card_ranks(&cards));
+        assert_eq!(HandType::ThreeOfAKind, hand_type("TTT98"));
     }
 
     #[test]
     fn parse_four_of_a_kind() {
-        let cards = vec![
-            ExtCardRank::Base(CardRank::T),
-            ExtCardRank::Base(CardRank::T),
-            ExtCardRank::Base(CardRank::T),
-            ExtCardRank::Base(CardRank::T),
-            ExtCardRank::Base(CardRank::_8),
-        ];
-        let expected = HandType::FourOfAKind;
-        assert_eq!(expected, HandType::from_card_ranks(&cards));
+        assert_eq!(HandType::FourOfAKind, hand_type("TTTT8"));
     }
 
     #[test]
     fn parse_full_house() {
-        let cards = vec![
-            ExtCardRank::Base(CardRank::_2),
-            ExtCardRank::Base(CardRank::_3),
-            ExtCardRank::Base(CardRank::_3),
-            ExtCardRank::Base(CardRank::_3),
-            ExtCardRank::Base(CardRank::_2),
-        ];
-        let expected = HandType::FullHouse;
-        assert_eq!(expected, HandType::from_card_ranks(&cards));
+        assert_eq!(HandType::FullHouse, hand_type("23332"));
+    }
+
+    // Independent classification from the number of distinct ranks and the
+    // number of equal pairs of cards
+    fn classify_by_pairs(cards: &[Card]) -> HandType {
+        let mut distinct = cards.to_vec();
+        distinct.sort();
+        distinct.dedup();
+        let pairs = (0..cards.len())
+            .flat_map(|i| (i + 1..cards.len()).map(move |j| (i, j)))
+            .filter(|(i, j)| cards[*i] == cards[*j])
+            .count();
+        match (distinct.len(), pairs) {
+            (1, _) => HandType::FiveOfAKind,
+            (2, 6) => HandType::FourOfAKind,
+            (2, _) => HandType::FullHouse,
+            (3, 3) => HandType::ThreeOfAKind,
+            (3, _) => HandType::TwoPair,
+            (4, _) => HandType::OnePair,
+            (_, _) => HandType::HighCard,
+        }
+    }
+
+    // Every ordered 5-card hand, 13^5 of them
+    fn all_hands() -> Vec<Vec<Card>> {
+        (0..13usize.pow(5))
+            .map(|mut n| {
+                (0..5)
+                    .map(|_| {
+                        let card = Card::Rank((n % 13) as u8);
+                        n /= 13;
+                        card
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn classify_all_hands() {
+        let mut counts: Vec<(HandType, usize)> = vec![];
+        for hand in all_hands() {
+            let hand_type = HandType::from_cards_with_wildcards(&hand);
+            assert_eq!(classify_by_pairs(&hand), hand_type, "{:?}", hand);
+            match counts.iter_mut().find(|(t, _)| t == &hand_type) {
+                Some((_, n)) => *n += 1,
+                None => counts.push((hand_type, 1)),
+            }
+        }
+        counts.sort();
+        assert_eq!(
+            vec![
+                (HandType::HighCard, 154440),
+                (HandType::OnePair, 171600),
+                (HandType::TwoPair, 25740),
+                (HandType::ThreeOfAKind, 17160),
+                (HandType::FullHouse, 1560),
+                (HandType::FourOfAKind, 780),
+                (HandType::FiveOfAKind, 13),
+            ],
+            counts
+        );
+    }
+
+    #[test]
+    fn classify_histograms() {
+        assert_eq!(HandType::FiveOfAKind, HandType::from_histogram(&[6]));
+        assert_eq!(HandType::FullHouse, HandType::from_histogram(&[3, 3]));
+        assert_eq!(HandType::TwoPair, HandType::from_histogram(&[2, 2, 2]));
+        assert_eq!(HandType::OnePair, HandType::from_histogram(&[2, 1]));
+        assert_eq!(HandType::HighCard, HandType::from_histogram(&[1, 1]));
+        assert_eq!(HandType::HighCard, HandType::from_histogram(&[]));
+    }
+
+    // Best hand type from trying every rank in place of the jokers
+    fn jokers_by_substitution(cards: &[Card]) -> HandType {
+        (0..13)
+            .map(|rank| {
+                let substituted: Vec<Card> = cards
+                    .iter()
+                    .map(|c| match c {
+                        Card::Wild(_) => Card::Rank(rank),
+                        _ => *c,
+                    })
+                    .collect();
+                HandType::from_cards_with_wildcards(&substituted)
+            })
+            .max()
+            .unwrap()
+    }
+
+    // Every multiset of size cards, the hand type doesn't depend on order
+    fn multisets(ranks: &[Card], size: usize) -> Vec<Vec<Card>> {
+        if size == 0 {
+            return vec![vec![]];
+        }
+        (0..ranks.len())
+            .flat_map(|i| {
+                multisets(&ranks[i..], size - 1)
+                    .into_iter()
+                    .map(move |mut rest| {
+                        rest.insert(0, ranks[i]);
+                        rest
+                    })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn jokers_match_substitution() {
+        let rules = Ruleset::camel_jokers();
+        let ranks: Vec<Card> = rules
+            .ranks
+            .iter()
+            .map(|c| rules.card(*c).unwrap())
+            .collect();
+        let hands = multisets(&ranks, 5);
+        assert_eq!(6188, hands.len());
+        for hand in hands {
+            assert_eq!(
+                jokers_by_substitution(&hand),
+                rules.hand_type(&hand),
+                "{:?}",
+                hand
+            );
+        }
+    }
+
+    #[test]
+    fn jokers_promote() {
+        let rules = Ruleset::camel_jokers();
+        assert_eq!(HandType::TwoPair, hand_type("QJJQ2"));
+        assert_eq!(
+            HandType::FourOfAKind,
+            rules.hand_type(&rules.cards("QJJQ2").unwrap())
+        );
+        assert_eq!(
+            HandType::FiveOfAKind,
+            rules.hand_type(&rules.cards("JJJJJ").unwrap())
+        );
+    }
+
+    fn example() -> Vec<String> {
+        [
+            "32T3K 765",
+            "T55J5 684",
+            "KK677 28",
+            "KTJJT 220",
+            "QQQJA 483",
+        ]
+        .iter()
+        .map(|l| l.to_string())
+        .collect()
+    }
+
+    fn play(rules: &Ruleset, lines: &[String]) -> u64 {
+        winnings(parse_hands(lines, |l| rules.hand(l)).unwrap())
+    }
+
+    #[test]
+    fn example_rulesets() {
+        assert_eq!(6440, play(&Ruleset::camel(), &example()));
+        assert_eq!(5905, play(&Ruleset::camel_jokers(), &example()));
+        let rules = Ruleset::from_spec(Ruleset::camel(), "wild=J").unwrap();
+        assert_eq!(Ruleset::camel_jokers(), rules);
+        let rules = Ruleset::from_spec(Ruleset::camel_jokers(), "size=6").unwrap();
+        assert_eq!(vec!['J'], rules.wildcards);
+    }
+
+    #[test]
+    fn house_rulesets() {
+        // Twos are wild as well as jacks
+        let rules = Ruleset::from_spec(Ruleset::camel(), "wild=J2").unwrap();
+        assert_eq!(
+            HandType::FiveOfAKind,
+            rules.hand_type(&rules.cards("QJ2QQ").unwrap())
+        );
+        assert!(rules.card('2') < rules.card('3'));
+        assert!(rules.card('J') < rules.card('3'));
+
+        // Six card hands
+        let rules = Ruleset::from_spec(Ruleset::camel(), "size=6").unwrap();
+        let hand = rules.hand("QQQ22K 10").unwrap();
+        assert_eq!(HandType::FullHouse, hand.hand_type);
+        assert_eq!(6, hand.hand.len());
+
+        // Reversed rank order, grouped tie-break
+        let rules =
+            Ruleset::from_spec(Ruleset::camel(), "ranks=AKQJT98765432;tiebreak=grouped").unwrap();
+        assert!(rules.card('2') > rules.card('A'));
+        let a = rules.hand("2233A 1").unwrap();
+        let b = rules.hand("A3322 1").unwrap();
+        assert_eq!(a.hand, b.hand);
+        let c = rules.hand("AA332 1").unwrap();
+        assert!(a > c);
+    }
+
+    #[test]
+    fn invalid_rulesets() {
+        assert!(Ruleset::from_spec(Ruleset::camel(), "wild=X").is_err());
+        assert!(Ruleset::from_spec(Ruleset::camel(), "ranks=AA23").is_err());
+        assert!(Ruleset::from_spec(Ruleset::camel(), "size=0").is_err());
+        assert!(Ruleset::from_spec(Ruleset::camel(), "tiebreak=random").is_err());
+        assert!(Ruleset::from_spec(Ruleset::camel(), "jokers").is_err());
+        assert!(Ruleset::from_spec(Ruleset::camel(), "colour=red").is_err());
+    }
+
+    #[test]
+    fn strict_parsing() {
+        let rules = Ruleset::camel();
+        let error = |line: &str| rules.hand(line).unwrap_err();
+        assert_eq!(ParseError::UnknownCard('X'), error("32X3K 765"));
+        assert_eq!(ParseError::UnknownCard('t'), error("32t3K 765"));
+        assert_eq!(
+            ParseError::HandSize {
+                expected: 5,
+                found: 4
+            },
+            error("32T3 765")
+        );
+        assert_eq!(
+            ParseError::HandSize {
+                expected: 5,
+                found: 6
+            },
+            error("32T3KK 765")
+        );
+        assert_eq!(ParseError::MissingBid, error("32T3K"));
+        assert_eq!(
+            ParseError::HandSize {
+                expected: 5,
+                found: 0
+            },
+            error("")
+        );
+        assert_eq!(
+            ParseError::NegativeBid("-765".to_string()),
+            error("32T3K -765")
+        );
+        assert_eq!(
+            ParseError::InvalidBid("7x5".to_string()),
+            error("32T3K 7x5")
+        );
+        assert_eq!(
+            ParseError::UnexpectedField("5".to_string()),
+            error("32T3K 76 5")
+        );
+
+        let mut lines = example();
+        lines.insert(2, String::new());
+        assert_eq!(6440, play(&rules, &lines));
+        lines[4] = "KTJJT".to_string();
+        assert_eq!(
+            Err((5, ParseError::MissingBid)),
+            parse_hands(&lines, |l| rules.hand(l))
+        );
+
+        let poker = Poker::new();
+        let error = |line: &str| poker.hand(line).unwrap_err();
+        assert_eq!(ParseError::MissingBid, error("AS KD 7H 7C 2S"));
+        assert_eq!(ParseError::UnknownSuit('X'), error("AS KD 7H 7C 2X 1"));
+        assert_eq!(
+            ParseError::DuplicateCard("AS".to_string()),
+            error("AS AS AS AS AS 1")
+        );
+        assert_eq!(
+            ParseError::DuplicateCard("7H".to_string()),
+            error("AS 7H KD 7C 7H 1")
+        );
+        assert_eq!(ParseError::UnknownCard('1'), error("AS KD 7H 7C 1S 1"));
+        assert_eq!(
+            ParseError::InvalidCard("10S".to_string()),
+            error("AS KD 7H 7C 10S 1")
+        );
+        assert_eq!(
+            ParseError::HandSize {
+                expected: 5,
+                found: 4
+            },
+            error("AS KD 7H 7C 1")
+        );
+        assert_eq!(
+            ParseError::NegativeBid("-1".to_string()),
+            error("AS KD 7H 7C 2S -1")
+        );
+    }
+
+    #[test]
+    fn compare_hands() {
+        let rules = Ruleset::camel();
+        let deal = |cards: &str| rules.deal(cards, 0).unwrap();
+        assert_eq!(
+            (Ordering::Less, Decider::HandType),
+            decide(&deal("32T3K"), &deal("KK677"))
+        );
+        assert_eq!(
+            (Ordering::Greater, Decider::Card(1)),
+            decide(&deal("KK677"), &deal("KTJJT"))
+        );
+        assert_eq!(
+            (Ordering::Equal, Decider::Tie),
+            decide(&deal("KK677"), &deal("KK677"))
+        );
+        assert_eq!(
+            "KK677 wins: both two pair, card 2 is K against T",
+            explain(&rules, &deal("KTJJT"), &deal("KK677"))
+        );
+
+        let rules = Ruleset::camel_jokers();
+        let deal = |cards: &str| rules.deal(cards, 0).unwrap();
+        assert_eq!(
+            "KTJJT wins: four of a kind beats two pair",
+            explain(&rules, &deal("KK677"), &deal("KTJJT"))
+        );
+        assert_eq!(
+            "QQQQ2 wins: both four of a kind, card 4 is Q against J",
+            explain(&rules, &deal("QQQJ2"), &deal("QQQQ2"))
+        );
+    }
+
+    #[test]
+    fn hand_type_stats() {
+        let plain = parse_hands(&example(), |l| Ruleset::camel().hand(l)).unwrap();
+        let jokers = parse_hands(&example(), |l| Ruleset::camel_jokers().hand(l)).unwrap();
+        assert_eq!(
+            vec![
+                (HandType::OnePair, 1, 765),
+                (HandType::TwoPair, 2, 28 + 220),
+                (HandType::ThreeOfAKind, 2, 684 + 483),
+            ],
+            type_stats(&plain)
+        );
+        assert_eq!(
+            vec![
+                (HandType::OnePair, 1, 765),
+                (HandType::TwoPair, 1, 28),
+                (HandType::FourOfAKind, 3, 684 + 220 + 483),
+            ],
+            type_stats(&jokers)
+        );
+        let promoted: Vec<(&str, HandType, HandType)> = promotions(&plain, &jokers)
+            .into_iter()
+            .map(|(h, to)| (h.dealt.as_str(), h.hand_type.clone(), to.clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("T55J5", HandType::ThreeOfAKind, HandType::FourOfAKind),
+                ("KTJJT", HandType::TwoPair, HandType::FourOfAKind),
+                ("QQQJA", HandType::ThreeOfAKind, HandType::FourOfAKind),
+            ],
+            promoted
+        );
+    }
+
+    #[test]
+    fn poker_hand_types() {
+        let poker = Poker::new();
+        let hand_type = |cards: &str| poker.hand(&format!("{} 1", cards)).unwrap().hand_type;
+        assert_eq!(HandType::HighCard, hand_type("AS KD 7H 3C 2S"));
+        assert_eq!(HandType::OnePair, hand_type("AS KD 7H 7C 2S"));
+        assert_eq!(HandType::TwoPair, hand_type("AS KD KH 7C 7S"));
+        assert_eq!(HandType::ThreeOfAKind, hand_type("7S KD 7H 7C 2S"));
+        assert_eq!(HandType::Straight, hand_type("9S TD JH QC KS"));
+        assert_eq!(HandType::Straight, hand_type("AS 2D 3H 4C 5S"));
+        assert_eq!(HandType::Straight, hand_type("TS JD QH KC AS"));
+        assert_eq!(HandType::HighCard, hand_type("QS KD AH 2C 3S"));
+        assert_eq!(HandType::Flush, hand_type("AH KH 7H 3H 2H"));
+        assert_eq!(HandType::FullHouse, hand_type("7S KD 7H 7C KS"));
+        assert_eq!(HandType::FourOfAKind, hand_type("7S 7D 7H 7C KS"));
+        assert_eq!(HandType::StraightFlush, hand_type("5D 4D 3D 2D AD"));
+    }
+
+    #[test]
+    fn poker_tie_breaks() {
+        let poker = Poker::new();
+        let hand = |cards: &str| poker.hand(&format!("{} 1", cards)).unwrap();
+        // Pair of sevens, decided by the first kicker that differs
+        assert!(hand("AS KD 7H 7C 2S") > hand("AS QD 7H 7C 3S"));
+        // Higher pair wins no matter the kickers
+        assert!(hand("8S 8D 2H 3C 4S") > hand("7S 7D AH KC QS"));
+        // Two pair compares the top pair first
+        assert!(hand("KS KD 2H 2C 3S") > hand("QS QD JH JC AS"));
+        // The wheel is the lowest straight
+        assert!(hand("2S 3D 4H 5C 6S") > hand("AS 2D 3H 4C 5S"));
+        // Full house compares the triple first
+        assert!(hand("3S 3D 3H 2C 2S") > hand("2S 2D 2H AC AS"));
+        // Equal ranks tie regardless of suit
+        assert_eq!(hand("AS KD 7H 7C 2S").hand, hand("AD KS 7C 7H 2D").hand);
+    }
+
+    #[test]
+    fn poker_winnings() {
+        let poker = Poker::new();
+        let hands: Vec<Hand> = [
+            "2H 3D 5S 9C KD 10",
+            "2C 3H 4S 8C AH 20",
+            "2H 4S 4C 2D 4H 30",
+            "2S 8S AS QS 3S 40",
+        ]
+        .iter()
+        .map(|l| poker.hand(l).unwrap())
+        .collect();
+        // High card K, high card A, flush, full house
+        assert_eq!(10 + 20 * 2 + 40 * 3 + 30 * 4, winnings(hands));
     }
 }
@@ -1,24 +1,124 @@
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::env;
+use std::fmt;
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::process;
 use std::str::FromStr;
+use std::time::Instant;
+
+use num::integer::Integer;
+use regex::Regex;
 
 use parse_display::FromStr;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 enum Instruction {
-    Right,
     Left,
+    Right,
 }
 
 impl Instruction {
-    fn from_char(c: char) -> Instruction {
+    fn from_char(c: char) -> Option<Instruction> {
         match c {
-            'R' => Instruction::Right,
-            'L' => Instruction::Left,
-            _ => Instruction::Left,
+            'R' => Some(Instruction::Right),
+            'L' => Some(Instruction::Left),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ParseError {
+    NoInstructions,
+    InvalidInstruction(char),
+    InvalidNode(String),
+    MissingNode(String),
+    DuplicateNode(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoInstructions => write!(f, "missing left/right instructions"),
+            Self::InvalidInstruction(c) => write!(f, "invalid instruction '{}'", c),
+            Self::InvalidNode(s) => write!(f, "expected 'AAA = (BBB, CCC)', got '{}'", s),
+            Self::MissingNode(name) => write!(f, "node {} is not defined", name),
+            Self::DuplicateNode(name) => write!(f, "node {} is defined twice", name),
+        }
+    }
+}
+
+// Selects nodes by name, written as "AAA" or "exact:AAA", "suffix:Z" or
+// "regex:^1.A$"
+#[derive(Debug)]
+enum NodePattern {
+    Exact(String),
+    Suffix(String),
+    Regex(Regex),
+}
+
+impl FromStr for NodePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("exact", name)) => Ok(NodePattern::Exact(name.to_string())),
+            Some(("suffix", suffix)) => Ok(NodePattern::Suffix(suffix.to_string())),
+            Some(("regex", re)) => Regex::new(re)
+                .map(NodePattern::Regex)
+                .map_err(|e| format!("invalid regex '{}': {}", re, e)),
+            Some((kind, _)) => Err(format!("unknown pattern kind '{}'", kind)),
+            None => Ok(NodePattern::Exact(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for NodePattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Exact(name) => write!(f, "{}", name),
+            Self::Suffix(suffix) => write!(f, "*{}", suffix),
+            Self::Regex(re) => write!(f, "/{}/", re),
+        }
+    }
+}
+
+impl NodePattern {
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            Self::Exact(exact) => name == exact,
+            Self::Suffix(suffix) => name.ends_with(suffix.as_str()),
+            Self::Regex(re) => re.is_match(name),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum WalkError {
+    NoStart(String),
+    AmbiguousStart { pattern: String, count: usize },
+    // The walk came back to a node at the same instruction index without
+    // passing a goal, so it never will
+    Unreachable { start: String, steps: u64 },
+}
+
+impl fmt::Display for WalkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoStart(pattern) => write!(f, "no node matches start {}", pattern),
+            Self::AmbiguousStart { pattern, count } => write!(
+                f,
+                "start {} matches {} nodes, walking needs exactly one",
+                pattern, count
+            ),
+            Self::Unreachable { start, steps } => write!(
+                f,
+                "no goal is reachable from {}, the walk repeats itself after {} steps",
+                start, steps
+            ),
         }
     }
 }
@@ -31,95 +131,457 @@ struct Map {
     right: String,
 }
 
-fn parse() -> Vec<String> {
+// Nodes interned in input order, so a walk is a lookup in `edges` by index
+// instead of hashing names
+struct Network {
+    names: Vec<String>,
+    ids: HashMap<String, u32>,
+    // Left and right successor of each node
+    edges: Vec<[u32; 2]>,
+}
+
+impl Network {
+    // Maps come with their line numbers, to report references to nodes that
+    // are never defined
+    fn from_maps(maps: &[(usize, Map)]) -> Result<Network, (usize, ParseError)> {
+        let names: Vec<String> = maps.iter().map(|(_, m)| m.root.clone()).collect();
+        let mut ids: HashMap<String, u32> = HashMap::new();
+        for (i, (line_no, m)) in maps.iter().enumerate() {
+            if ids.insert(m.root.clone(), i as u32).is_some() {
+                return Err((*line_no, ParseError::DuplicateNode(m.root.clone())));
+            }
+        }
+        let edges = maps
+            .iter()
+            .map(|(line_no, m)| {
+                let id = |name: &String| {
+                    ids.get(name)
+                        .copied()
+                        .ok_or((*line_no, ParseError::MissingNode(name.clone())))
+                };
+                Ok([id(&m.left)?, id(&m.right)?])
+            })
+            .collect::<Result<Vec<[u32; 2]>, (usize, ParseError)>>()?;
+        Ok(Network { names, ids, edges })
+    }
+
+    fn id(&self, name: &str) -> Option<u32> {
+        self.ids.get(name).copied()
+    }
+
+    // Nodes matching the pattern, in input order
+    fn select(&self, pattern: &NodePattern) -> Vec<u32> {
+        match pattern {
+            NodePattern::Exact(name) => self.id(name).into_iter().collect(),
+            _ => (0..self.names.len() as u32)
+                .filter(|n| pattern.matches(&self.names[*n as usize]))
+                .collect(),
+        }
+    }
+
+    fn step(&self, node: u32, instruction: Instruction) -> u32 {
+        self.edges[node as usize][instruction as usize]
+    }
+
+    // Steps from start until a node in goal is reached
+    fn steps(
+        &self,
+        instructions: &[Instruction],
+        start: u32,
+        goal: &[bool],
+    ) -> Result<u64, WalkError> {
+        let len = instructions.len();
+        let mut seen: HashSet<(u32, usize)> = HashSet::new();
+        let mut node = start;
+        let mut counter: u64 = 0;
+        loop {
+            if !seen.insert((node, counter as usize % len)) {
+                return Err(WalkError::Unreachable {
+                    start: self.names[start as usize].clone(),
+                    steps: counter,
+                });
+            }
+            node = self.step(node, instructions[counter as usize % len]);
+            counter += 1;
+            if goal[node as usize] {
+                return Ok(counter);
+            }
+        }
+    }
+
+    fn matching(&self, pattern: &NodePattern) -> Vec<bool> {
+        self.names.iter().map(|n| pattern.matches(n)).collect()
+    }
+
+    // Walks from start until a (node, instruction index) state repeats,
+    // which is where the walk starts going round in a cycle. Only visited
+    // states are kept, not the whole state space
+    fn ghost(&self, instructions: &[Instruction], start: u32, goal: &[bool]) -> Ghost {
+        let len = instructions.len();
+        let mut seen: HashMap<(u32, usize), u64> = HashMap::new();
+        let mut hits: Vec<u64> = vec![];
+        let mut node = start;
+        let mut t: u64 = 0;
+        loop {
+            let state = (node, t as usize % len);
+            if let Some(&cycle_start) = seen.get(&state) {
+                let cycle_len = t - cycle_start;
+                let (cycle, transient): (Vec<u64>, Vec<u64>) =
+                    hits.into_iter().partition(|h| *h >= cycle_start);
+                return Ghost {
+                    start,
+                    transient: transient.into_iter().filter(|h| *h > 0).collect(),
+                    cycle_start,
+                    cycle_len,
+                    // Standing on a goal at the start isn't a step, the
+                    // first hit is one time round the cycle later
+                    cycle: cycle
+                        .into_iter()
+                        .map(|h| if h == 0 { cycle_len } else { h })
+                        .collect(),
+                };
+            }
+            seen.insert(state, t);
+            if goal[node as usize] {
+                hits.push(t);
+            }
+            node = self.step(node, instructions[t as usize % len]);
+            t += 1;
+        }
+    }
+}
+
+// Where one ghost's walk reaches goal nodes: at the transient steps, before
+// the walk enters its cycle, and then at each cycle hit plus any number of
+// cycle lengths
+#[derive(Debug, PartialEq)]
+struct Ghost {
+    start: u32,
+    transient: Vec<u64>,
+    cycle_start: u64,
+    cycle_len: u64,
+    cycle: Vec<u64>,
+}
+
+#[derive(Debug, PartialEq)]
+enum GhostError {
+    NoGhosts,
+    NeverAligned,
+    Overflow,
+}
+
+impl fmt::Display for GhostError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoGhosts => write!(f, "no start nodes"),
+            Self::NeverAligned => write!(f, "the ghosts are never on goal nodes together"),
+            Self::Overflow => write!(f, "step count overflowed u128"),
+        }
+    }
+}
+
+// Steps at which every ghost so far is on a goal node: the exact steps, and
+// progressions of a first step and the period after which it repeats
+#[derive(Debug, Default)]
+struct Schedule {
+    exact: Vec<u128>,
+    periodic: Vec<(u128, u128)>,
+}
+
+impl Schedule {
+    fn of(ghost: &Ghost) -> Schedule {
+        Schedule {
+            exact: ghost.transient.iter().map(|t| *t as u128).collect(),
+            periodic: ghost
+                .cycle
+                .iter()
+                .map(|t| (*t as u128, ghost.cycle_len as u128))
+                .collect(),
+        }
+    }
+
+    fn contains(&self, t: u128) -> bool {
+        self.exact.contains(&t)
+            || self
+                .periodic
+                .iter()
+                .any(|(first, period)| t >= *first && Integer::is_multiple_of(&(t - first), period))
+    }
+
+    fn intersect(&self, other: &Schedule) -> Result<Schedule, GhostError> {
+        let mut exact: Vec<u128> = self
+            .exact
+            .iter()
+            .filter(|t| other.contains(**t))
+            .chain(other.exact.iter().filter(|t| self.contains(**t)))
+            .copied()
+            .collect();
+        exact.sort();
+        exact.dedup();
+        let mut periodic = vec![];
+        for a in &self.periodic {
+            for b in &other.periodic {
+                if let Some(p) = crt(*a, *b)? {
+                    periodic.push(p);
+                }
+            }
+        }
+        periodic.sort();
+        periodic.dedup();
+        Ok(Schedule { exact, periodic })
+    }
+
+    fn first(&self) -> Option<u128> {
+        let periodic = self.periodic.iter().map(|(first, _)| *first);
+        self.exact.iter().copied().chain(periodic).min()
+    }
+}
+
+// First step on or after both starts that is in both progressions, and the
+// period of the combined progression
+fn crt((a, m): (u128, u128), (b, n): (u128, u128)) -> Result<Option<(u128, u128)>, GhostError> {
+    let big = |x: u128| i128::try_from(x).map_err(|_| GhostError::Overflow);
+    let (a, m, b, n) = (big(a)?, big(m)?, big(b)?, big(n)?);
+    let e = m.extended_gcd(&n);
+    if (b - a) % e.gcd != 0 {
+        return Ok(None);
+    }
+    let modulus = n / e.gcd;
+    let lcm = (m / e.gcd).checked_mul(n).ok_or(GhostError::Overflow)?;
+    // t = a + m * k with k = (b - a) / g * x (mod n / g)
+    let k = ((b - a) / e.gcd % modulus)
+        .checked_mul(e.x % modulus)
+        .ok_or(GhostError::Overflow)?
+        .rem_euclid(modulus);
+    let mut t = m
+        .checked_mul(k)
+        .and_then(|d| d.checked_add(a))
+        .ok_or(GhostError::Overflow)?
+        .rem_euclid(lcm);
+    let floor = a.max(b);
+    if t < floor {
+        let rounds = (floor - t + lcm - 1) / lcm;
+        t = rounds
+            .checked_mul(lcm)
+            .and_then(|d| d.checked_add(t))
+            .ok_or(GhostError::Overflow)?;
+    }
+    Ok(Some((t as u128, lcm as u128)))
+}
+
+fn ghosts(
+    instructions: &[Instruction],
+    network: &Network,
+    start: &NodePattern,
+    goal: &NodePattern,
+) -> Vec<Ghost> {
+    let goal = network.matching(goal);
+    network
+        .select(start)
+        .into_iter()
+        .map(|start| network.ghost(instructions, start, &goal))
+        .collect()
+}
+
+fn align(ghosts: &[Ghost]) -> Result<u128, GhostError> {
+    let (first, rest) = ghosts.split_first().ok_or(GhostError::NoGhosts)?;
+    let mut schedule = Schedule::of(first);
+    for ghost in rest {
+        schedule = schedule.intersect(&Schedule::of(ghost))?;
+    }
+    schedule.first().ok_or(GhostError::NeverAligned)
+}
+
+fn parse_to_string() -> Vec<String> {
     let file = File::open("input.txt").expect("not found");
     let reader = BufReader::new(file);
     reader.lines().map(Result::unwrap).collect::<Vec<String>>()
 }
 
-fn part_1() -> u64 {
-    let lines = parse();
-    let mut line_iter = lines.iter();
-    let instructions: Vec<Instruction> = line_iter
-        .next()
-        .unwrap()
+// Errors carry the line number
+fn parse_lines(lines: &[String]) -> Result<(Vec<Instruction>, Network), (usize, ParseError)> {
+    let instructions = lines
+        .first()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .ok_or((1, ParseError::NoInstructions))?
         .chars()
-        .map(|c| Instruction::from_char(c))
-        .collect();
-    println!("{:?}", instructions);
-    line_iter.next(); // empty line
+        .map(|c| Instruction::from_char(c).ok_or((1, ParseError::InvalidInstruction(c))))
+        .collect::<Result<Vec<Instruction>, (usize, ParseError)>>()?;
 
-    let map = line_iter
-        .map(|l| Map::from_str(l).unwrap())
-        .collect::<Vec<Map>>();
-    let mut path = HashMap::new();
-    map.iter().for_each(|m| {
-        path.insert(m.root.as_str(), (m.left.as_str(), m.right.as_str()));
-    });
-    let root = "AAA";
-    let mut next: &str = root;
-    let mut counter: u64 = 0;
-
-    for i in instructions.iter().cycle() {
-        let (left, right) = path[next];
-
-        next = match i {
-            Instruction::Left => left,
-            Instruction::Right => right,
-        };
+    let maps = lines
+        .iter()
+        .enumerate()
+        .skip(1)
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| {
+            Map::from_str(l.trim())
+                .map(|m| (i + 1, m))
+                .map_err(|_| (i + 1, ParseError::InvalidNode(l.to_string())))
+        })
+        .collect::<Result<Vec<(usize, Map)>, (usize, ParseError)>>()?;
+    Ok((instructions, Network::from_maps(&maps)?))
+}
 
-        counter += 1;
-        if next == "ZZZ" {
-            break;
+fn parse() -> (Vec<Instruction>, Network) {
+    parse_lines(&parse_to_string()).unwrap_or_else(|(line_no, e)| {
+        eprintln!("input.txt:{}: {}", line_no, e);
+        process::exit(1);
+    })
+}
+
+fn or_exit<T, E: fmt::Display>(result: Result<T, E>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    })
+}
+
+fn part_1(
+    instructions: &[Instruction],
+    network: &Network,
+    start: &NodePattern,
+    goal: &NodePattern,
+) -> Result<u64, WalkError> {
+    let start = match network.select(start)[..] {
+        [] => return Err(WalkError::NoStart(start.to_string())),
+        [node] => node,
+        ref nodes => {
+            return Err(WalkError::AmbiguousStart {
+                pattern: start.to_string(),
+                count: nodes.len(),
+            })
+        }
+    };
+    network.steps(instructions, start, &network.matching(goal))
+}
+
+fn part_2(
+    instructions: &[Instruction],
+    network: &Network,
+    start: &NodePattern,
+    goal: &NodePattern,
+) -> Result<u128, GhostError> {
+    align(&ghosts(instructions, network, start, goal))
+}
+
+// Steps from each start on its own to the first goal
+fn reach(instructions: &[Instruction], network: &Network, start: &NodePattern, goal: &NodePattern) {
+    let goal = network.matching(goal);
+    for node in network.select(start) {
+        let name = &network.names[node as usize];
+        match network.steps(instructions, node, &goal) {
+            Ok(steps) => println!("{}: {} steps", name, steps),
+            Err(e) => println!("{}: {}", name, e),
         }
     }
-    counter
 }
 
-fn part_2() -> u64 {
-    let lines = parse();
-    let mut line_iter = lines.iter();
-    let instructions: Vec<Instruction> = line_iter
-        .next()
-        .unwrap()
-        .chars()
-        .map(|c| Instruction::from_char(c))
+fn report(
+    instructions: &[Instruction],
+    network: &Network,
+    start: &NodePattern,
+    goal: &NodePattern,
+) {
+    for ghost in ghosts(instructions, network, start, goal) {
+        println!(
+            "{}: cycle of {} steps from step {}",
+            network.names[ghost.start as usize], ghost.cycle_len, ghost.cycle_start
+        );
+        println!("  before the cycle: {:?}", ghost.transient);
+        println!("  in the cycle:     {:?}", ghost.cycle);
+    }
+}
+
+// Pattern from the variable, or the default for the part
+fn pattern(var: &str, default: &str) -> NodePattern {
+    let value = env::var(var).unwrap_or(default.to_string());
+    value.parse().unwrap_or_else(|e| {
+        eprintln!("{}: {}", var, e);
+        process::exit(1);
+    })
+}
+
+// A random network of node_count nodes with names like the puzzle's, three
+// letters or more
+fn generate(node_count: usize, instruction_count: usize) -> Vec<String> {
+    let mut seed: u64 = 0x2023_0008;
+    let mut next_random = |bound: usize| {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 33) as usize % bound
+    };
+    let mut width = 3;
+    while 26usize.pow(width) < node_count {
+        width += 1;
+    }
+    let name = |mut n: usize| {
+        (0..width)
+            .map(|_| {
+                let c = (b'A' + (n % 26) as u8) as char;
+                n /= 26;
+                c
+            })
+            .collect::<String>()
+    };
+
+    let instructions: String = (0..instruction_count)
+        .map(|_| if next_random(2) == 0 { 'L' } else { 'R' })
         .collect();
-    line_iter.next(); // empty line
+    let mut lines = vec![instructions, String::new()];
+    for n in 0..node_count {
+        let (left, right) = (next_random(node_count), next_random(node_count));
+        lines.push(format!("{} = ({}, {})", name(n), name(left), name(right)));
+    }
+    lines
+}
 
-    let map = line_iter
-        .map(|l| Map::from_str(l).unwrap())
-        .collect::<Vec<Map>>();
-    let mut path = HashMap::new();
-    map.iter().for_each(|m| {
-        path.insert(m.root.as_str(), (m.left.as_str(), m.right.as_str()));
-    });
-
-    let mut steps_per_path: Vec<u64> = vec![];
-
-    path.iter()
-        .filter(|(key, _)| key.ends_with('A'))
-        .for_each(|(key, _)| {
-            let mut counter: u64 = 0;
-            let mut next: &str = key;
-            for i in instructions.iter().cycle() {
-                let (left, right) = path[next];
-
-                next = match i {
-                    Instruction::Left => left,
-                    Instruction::Right => right,
-                };
+// Walks a generated network for a fixed number of steps with the interned
+// network and with the string map it replaced
+fn bench(node_count: usize, step_count: usize) {
+    let start = Instant::now();
+    let lines = generate(node_count, 293);
+    let generate_time = start.elapsed();
 
-                counter += 1;
-                if next.ends_with('Z') {
-                    break;
-                }
-            }
-            steps_per_path.push(counter);
-        });
-    steps_per_path
+    let start = Instant::now();
+    let (instructions, network) = parse_lines(&lines).unwrap();
+    let build_time = start.elapsed();
+
+    let maps: Vec<Map> = lines[2..]
+        .iter()
+        .map(|l| Map::from_str(l).unwrap())
+        .collect();
+    let path: HashMap<&str, (&str, &str)> = maps
         .iter()
-        .fold(1, |a, b| num::integer::lcm(a, *b))
+        .map(|m| (m.root.as_str(), (m.left.as_str(), m.right.as_str())))
+        .collect();
+
+    let start = Instant::now();
+    let mut name: &str = &maps[0].root;
+    for i in instructions.iter().cycle().take(step_count) {
+        let (left, right) = path[name];
+        name = match i {
+            Instruction::Left => left,
+            Instruction::Right => right,
+        };
+    }
+    let map_time = start.elapsed();
+
+    let start = Instant::now();
+    let mut node = 0;
+    for i in instructions.iter().cycle().take(step_count) {
+        node = network.step(node, *i);
+    }
+    let network_time = start.elapsed();
+
+    assert_eq!(name, network.names[node as usize]);
+    println!("nodes:    {}", node_count);
+    println!("steps:    {}", step_count);
+    println!("generate: {:?}", generate_time);
+    println!("intern:   {:?}", build_time);
+    println!("hashmap:  {:?}", map_time);
+    println!("interned: {:?}", network_time);
 }
 
 fn main() {
@@ -129,8 +591,302 @@ fn main() {
     };
 
     if part == "part1" {
-        println!("{}", part_1());
+        let (instructions, network) = parse();
+        let (start, goal) = (pattern("from", "AAA"), pattern("to", "ZZZ"));
+        println!(
+            "{}",
+            or_exit(part_1(&instructions, &network, &start, &goal))
+        );
     } else if part == "part2" {
-        println!("{}", part_2());
+        let (instructions, network) = parse();
+        let (start, goal) = (pattern("from", "suffix:A"), pattern("to", "suffix:Z"));
+        println!(
+            "{}",
+            or_exit(part_2(&instructions, &network, &start, &goal))
+        );
+    } else if part == "reach" {
+        let (instructions, network) = parse();
+        let (start, goal) = (pattern("from", "suffix:A"), pattern("to", "suffix:Z"));
+        reach(&instructions, &network, &start, &goal);
+    } else if part == "ghosts" {
+        let (instructions, network) = parse();
+        let (start, goal) = (pattern("from", "suffix:A"), pattern("to", "suffix:Z"));
+        report(&instructions, &network, &start, &goal);
+    } else if part == "bench" {
+        let node_count = match env::var("nodes") {
+            Ok(val) => val.parse::<usize>().expect("nodes must be a number"),
+            Err(_e) => 1_000_000,
+        };
+        let step_count = match env::var("steps") {
+            Ok(val) => val.parse::<usize>().expect("steps must be a number"),
+            Err(_e) => 10_000_000,
+        };
+        bench(node_count, step_count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(input: &str) -> Vec<String> {
+        input.lines().map(|l| l.to_string()).collect()
+    }
+
+    fn parse_network(lines: &[String]) -> (Vec<Instruction>, Network) {
+        parse_lines(lines).unwrap()
+    }
+
+    fn pattern(s: &str) -> NodePattern {
+        s.parse().unwrap()
+    }
+
+    fn walk(instructions: &[Instruction], network: &Network) -> Result<u64, WalkError> {
+        part_1(instructions, network, &pattern("AAA"), &pattern("ZZZ"))
+    }
+
+    fn ghost_walk(instructions: &[Instruction], network: &Network) -> Result<u128, GhostError> {
+        part_2(
+            instructions,
+            network,
+            &pattern("suffix:A"),
+            &pattern("suffix:Z"),
+        )
+    }
+
+    #[test]
+    fn node_patterns() {
+        assert!(pattern("AAA").matches("AAA"));
+        assert!(!pattern("AAA").matches("AAAA"));
+        assert!(pattern("exact:A:B").matches("A:B"));
+        assert!(pattern("suffix:Z").matches("11Z"));
+        assert!(!pattern("suffix:Z").matches("Z11"));
+        assert!(pattern("regex:^1.A$").matches("11A"));
+        assert!(!pattern("regex:^1.A$").matches("21A"));
+        assert!("regex:(".parse::<NodePattern>().is_err());
+        assert!("prefix:A".parse::<NodePattern>().is_err());
+    }
+
+    #[test]
+    fn custom_questions() {
+        let (instructions, network) = parse_network(&lines(
+            "LR\n\n11A = (11B, XXX)\n11B = (XXX, 11Z)\n11Z = (11B, XXX)\n22A = (22B, XXX)\n22B = (22C, 22C)\n22C = (22Z, 22Z)\n22Z = (22B, 22B)\nXXX = (XXX, XXX)",
+        ));
+        let steps =
+            |from: &str, to: &str| part_1(&instructions, &network, &pattern(from), &pattern(to));
+        assert_eq!(Ok(2), steps("11A", "11Z"));
+        assert_eq!(Ok(3), steps("22A", "22Z"));
+        assert_eq!(Ok(1), steps("22A", "regex:B|C"));
+        assert_eq!(
+            Err(WalkError::AmbiguousStart {
+                pattern: "*A".to_string(),
+                count: 2
+            }),
+            steps("suffix:A", "suffix:Z")
+        );
+        assert!(matches!(
+            steps("11A", "22Z"),
+            Err(WalkError::Unreachable { .. })
+        ));
+        // The 2-ghosts on their own are on a Z every third step
+        assert_eq!(
+            Ok(3),
+            part_2(
+                &instructions,
+                &network,
+                &pattern("regex:^2.A$"),
+                &pattern("suffix:Z")
+            )
+        );
+        assert_eq!(
+            Err(GhostError::NoGhosts),
+            part_2(
+                &instructions,
+                &network,
+                &pattern("suffix:Q"),
+                &pattern("suffix:Z")
+            )
+        );
+    }
+
+    #[test]
+    fn unreachable_goal() {
+        // ZZZ only leads back to itself
+        let (instructions, network) = parse_network(&lines(
+            "LR\n\nAAA = (BBB, CCC)\nBBB = (AAA, CCC)\nCCC = (BBB, AAA)\nZZZ = (AAA, AAA)",
+        ));
+        assert_eq!(
+            Err(WalkError::Unreachable {
+                start: "AAA".to_string(),
+                steps: 3
+            }),
+            walk(&instructions, &network)
+        );
+        let (instructions, network) = parse_network(&lines("L\n\nBBB = (BBB, BBB)"));
+        assert_eq!(
+            Err(WalkError::NoStart("AAA".to_string())),
+            walk(&instructions, &network)
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |input: &str| parse_lines(&lines(input)).err().unwrap();
+        assert_eq!(
+            (4, ParseError::MissingNode("ZZZ".to_string())),
+            error("L\n\nAAA = (BBB, BBB)\nBBB = (AAA, ZZZ)")
+        );
+        assert_eq!(
+            (1, ParseError::NoInstructions),
+            error("\n\nAAA = (AAA, AAA)")
+        );
+        assert_eq!(
+            (1, ParseError::InvalidInstruction('X')),
+            error("LXR\n\nAAA = (AAA, AAA)")
+        );
+        assert_eq!(
+            (3, ParseError::InvalidNode("AAA = AAA".to_string())),
+            error("L\n\nAAA = AAA")
+        );
+        assert_eq!(
+            (5, ParseError::DuplicateNode("AAA".to_string())),
+            error("L\n\nAAA = (ZZZ, ZZZ)\nZZZ = (ZZZ, ZZZ)\nAAA = (AAA, AAA)")
+        );
+    }
+
+    #[test]
+    fn example_part_1() {
+        let (instructions, network) = parse_network(&lines(
+            "RL\n\nAAA = (BBB, CCC)\nBBB = (DDD, EEE)\nCCC = (ZZZ, GGG)\nDDD = (DDD, DDD)\nEEE = (EEE, EEE)\nGGG = (GGG, GGG)\nZZZ = (ZZZ, ZZZ)",
+        ));
+        assert_eq!(Ok(2), walk(&instructions, &network));
+        let (instructions, network) = parse_network(&lines(
+            "LLR\n\nAAA = (BBB, BBB)\nBBB = (AAA, ZZZ)\nZZZ = (ZZZ, ZZZ)",
+        ));
+        assert_eq!(Ok(6), walk(&instructions, &network));
+    }
+
+    #[test]
+    fn example_part_2() {
+        let (instructions, network) = parse_network(&lines(
+            "LR\n\n11A = (11B, XXX)\n11B = (XXX, 11Z)\n11Z = (11B, XXX)\n22A = (22B, XXX)\n22B = (22C, 22C)\n22C = (22Z, 22Z)\n22Z = (22B, 22B)\nXXX = (XXX, XXX)",
+        ));
+        assert_eq!(Ok(6), ghost_walk(&instructions, &network));
+    }
+
+    // First step at which all ghosts are on goals, by walking them together
+    fn align_by_walking(
+        instructions: &[Instruction],
+        network: &Network,
+        limit: u64,
+    ) -> Option<u64> {
+        let goal = network.matching(&pattern("suffix:Z"));
+        let mut nodes: Vec<u32> = network
+            .names
+            .iter()
+            .enumerate()
+            .filter(|(_, name)| name.ends_with('A'))
+            .map(|(start, _)| start as u32)
+            .collect();
+        for (t, i) in instructions.iter().cycle().take(limit as usize).enumerate() {
+            nodes.iter_mut().for_each(|n| *n = network.step(*n, *i));
+            if nodes.iter().all(|n| goal[*n as usize]) {
+                return Some(t as u64 + 1);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn ghost_cycles() {
+        // 1A leads into a cycle of 3 nodes, 2A is on its cycle at the start
+        // and hits goals at two offsets, on 2Z and 2ZZ
+        let (instructions, network) = parse_network(&lines(
+            "L\n\n1A = (1Z, 1Z)\n1Z = (1B, 1B)\n1B = (1C, 1C)\n1C = (1D, 1D)\n1D = (1B, 1B)\n2A = (2Z, 2Z)\n2Z = (2B, 2B)\n2B = (2ZZ, 2ZZ)\n2ZZ = (2A, 2A)",
+        ));
+        let ghosts = ghosts(
+            &instructions,
+            &network,
+            &pattern("suffix:A"),
+            &pattern("suffix:Z"),
+        );
+        assert_eq!(
+            Ghost {
+                start: 0,
+                transient: vec![1],
+                cycle_start: 2,
+                cycle_len: 3,
+                cycle: vec![],
+            },
+            ghosts[0]
+        );
+        assert_eq!(vec![1, 3], ghosts[1].cycle);
+        assert_eq!(4, ghosts[1].cycle_len);
+        let schedule = Schedule::of(&ghosts[1]);
+        assert!([1, 3, 5, 7, 9].iter().all(|t| schedule.contains(*t)));
+        assert!(![2, 4, 6, 8].iter().any(|t| schedule.contains(*t)));
+        assert_eq!(Ok(1), align(&ghosts));
+    }
+
+    #[test]
+    fn ghosts_not_lcm() {
+        // Cycles of 4 and 6 with goals at offsets 3 and 1: the first hits
+        // are 3 and 1 and their lcm of 3 misses, 3 + 4k = 1 + 6j first at 7
+        let (instructions, network) = parse_network(&lines(
+            "R\n\n1A = (1A, 1B)\n1B = (1B, 1C)\n1C = (1C, 1Z)\n1Z = (1Z, 1A)\n2A = (2A, 2Z)\n2Z = (2Z, 2B)\n2B = (2B, 2C)\n2C = (2C, 2D)\n2D = (2D, 2E)\n2E = (2E, 2A)",
+        ));
+        assert_eq!(Ok(7), ghost_walk(&instructions, &network));
+        assert_eq!(Some(7), align_by_walking(&instructions, &network, 100));
+
+        // Even and odd hits never coincide
+        let (instructions, network) = parse_network(&lines(
+            "R\n\n1A = (1A, 1Z)\n1Z = (1Z, 1A)\n2A = (2A, 2B)\n2B = (2B, 2Z)\n2Z = (2Z, 2C)\n2C = (2C, 2A)",
+        ));
+        assert_eq!(
+            Err(GhostError::NeverAligned),
+            ghost_walk(&instructions, &network)
+        );
+        assert_eq!(None, align_by_walking(&instructions, &network, 1000));
+    }
+
+    #[test]
+    fn ghosts_random_networks() {
+        for seed in 0..200 {
+            let mut state: u64 = seed;
+            let mut next_random = |bound: usize| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 33) as usize % bound
+            };
+            let names = ["1A", "2A", "3A", "AZ", "BZ", "CC", "DD", "EE", "FZ"];
+            let instructions: String = (0..1 + next_random(4))
+                .map(|_| if next_random(2) == 0 { 'L' } else { 'R' })
+                .collect();
+            let mut input = vec![instructions, String::new()];
+            for name in names {
+                let (l, r) = (next_random(names.len()), next_random(names.len()));
+                input.push(format!("{} = ({}, {})", name, names[l], names[r]));
+            }
+            let (instructions, network) = parse_lines(&input).unwrap();
+            assert_eq!(
+                align_by_walking(&instructions, &network, 10_000).map(|t| t as u128),
+                ghost_walk(&instructions, &network).ok(),
+                "{:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn interned_edges() {
+        let (_, network) = parse_lines(&generate(1000, 7)).unwrap();
+        assert_eq!(1000, network.names.len());
+        assert_eq!(Some(0), network.id("AAA"));
+        assert_eq!(Some(26), network.id("ABA"));
+        assert_eq!(None, network.id("ZZZ"));
+        for (n, [left, right]) in network.edges.iter().enumerate() {
+            assert!((*left as usize) < 1000 && (*right as usize) < 1000, "{}", n);
+        }
     }
 }